@@ -11,6 +11,8 @@ use git2::Repository;
 use mp_fingerprint_type::{FirmwareFingerprint, MpFingerprint};
 
 const IGNORE_PATH_DEP_INJ: &str = ".cargo/config.toml";
+/// Top of RAM kept for application mailbox, must match `laplus_boots_app::MAILBOX_SIZE`
+const MAILBOX_RESERVED: u32 = 16;
 
 fn parse_memory_size(s: &str) -> Option<u32> {
     let re = regex::bytes::Regex::new(r"//.*|/\*.*?\*/").unwrap();
@@ -30,9 +32,14 @@ fn parse_memory_size(s: &str) -> Option<u32> {
 }
 
 fn main() -> Result<(), Error> {
-    println!("cargo:rustc-link-arg-bins=--nmagic");
-    println!("cargo:rustc-link-arg-bins=-Tlink.x");
-    println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
+    // host build is only for unit tests, it links as a normal executable
+    let is_target = std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() == Ok("arm");
+
+    if is_target {
+        println!("cargo:rustc-link-arg-bins=--nmagic");
+        println!("cargo:rustc-link-arg-bins=-Tlink.x");
+        println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
+    }
 
     // Read Memory X
     let memory_x = std::fs::read_to_string("memory.x").expect("Failed to read memory.x");
@@ -56,10 +63,37 @@ fn main() -> Result<(), Error> {
     println!("cargo:rustc-env=FLASH_ORIGIN={}", flash_origin);
     println!("cargo:rustc-env=FLASH_LENGTH={}", flash_length);
 
+    let ram_origin = memory_x
+        .lines()
+        .find(|line| line.contains("RAM"))
+        .and_then(|line| line.split("ORIGIN = ").nth(1))
+        .and_then(|s| s.split(',').next())
+        .and_then(parse_memory_size)
+        .expect("Failed to find delimiter next to RAM ORIGIN");
+
+    let ram_length = memory_x
+        .lines()
+        .find(|line| line.contains("RAM"))
+        .and_then(|line| line.split("LENGTH = ").nth(1))
+        .and_then(parse_memory_size)
+        .expect("Failed to parse RAM LENGTH");
+
+    // Set RAM_ORIGIN and LENGTH
+    println!("cargo:rustc-env=RAM_ORIGIN={}", ram_origin);
+    println!("cargo:rustc-env=RAM_LENGTH={}", ram_length);
+
+    // Stack starts below the mailbox, so it survives reset and startup
+    if is_target {
+        println!(
+            "cargo:rustc-link-arg-bins=--defsym=_stack_start={:#x}",
+            ram_origin + ram_length - MAILBOX_RESERVED
+        );
+    }
+
     // Get project name and version
     let metadata = MetadataCommand::new().no_deps().exec()?;
 
-    if let Some(package) = metadata.packages.first() {
+    if let Some(package) = metadata.root_package() {
         let project_name = &package.name;
         let project_version = package.version.to_string();
 
@@ -128,13 +162,15 @@ fn main() -> Result<(), Error> {
         "cargo:rustc-env=GIT_COMMIT_SHORT_HASH={}",
         hex::encode(format!("{}{}", commit_short_hash, short_dirty_str))
     );
-    println!("cargo:rustc-env=GIT_COMMIT_DATETIME={}", commit_datetime);
+    println!(
+        "cargo:rustc-env=GIT_COMMIT_DATETIME={}",
+        commit_datetime.trim()
+    );
 
     // Generate elf header fingerprint
     let metadata = MetadataCommand::new().no_deps().exec()?;
     let main_package = metadata
-        .packages
-        .first()
+        .root_package()
         .expect("Cargo.toml doesn't have metadata");
 
     let hw_feature: Vec<(String, String)> = std::env::vars()
@@ -153,6 +189,8 @@ fn main() -> Result<(), Error> {
         .unwrap()
         .replace('_', "-");
 
+    println!("cargo:rustc-env=HW_MODEL={}", feature_based_model_ver);
+
     let fingerprint = MpFingerprint {
         firmware_fingerprint: FirmwareFingerprint {
             model_name: "BillMock-HW".to_owned(), // this is const value
@@ -0,0 +1,120 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Image header placed right after vector table of application,
+//! at `REMAIN_OFFSET + APP_HEADER_OFFSET`. `image_crc` is computed over the
+//! whole image except the header itself, so `confirm` can be programmed later
+//! without breaking it. `confirm` is left erased (0xFF) by the packer.
+
+use crc::Crc;
+
+use crate::{FLASH_BASE, LAPLUS_CRC, REMAIN_OFFSET};
+
+/// Vector table of STM32G0 is 48 words
+pub const APP_HEADER_OFFSET: usize = 0xC0;
+pub const APP_HEADER_MAGIC: [u8; 4] = *b"LPLB";
+pub const APP_HEADER_VERSION: u8 = 1;
+/// Value of `confirm` once application has confirmed the trial boot
+pub const APP_CONFIRM_MAGIC: [u8; 8] = *b"CONFIRMD";
+pub const ERASED_CONFIRM: [u8; 8] = [0xFF; 8];
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct AppHeader {
+    pub magic: [u8; 4],
+    pub header_version: u8,
+    pub reserved: [u8; 3],
+    /// whole image length from application start, little endian
+    pub image_length: [u8; 4],
+    /// CRC32 of image excluding this header, little endian
+    pub image_crc: [u8; 4],
+    /// application version string, ASCII padded with zero
+    pub fw_version: [u8; 8],
+    /// double-word aligned, programmed once on confirmation
+    pub confirm: [u8; 8],
+}
+
+pub const APP_HEADER_SIZE: usize = core::mem::size_of::<AppHeader>();
+
+// packer and bootloader of other builds find fields at these offsets
+const _: () = assert!(APP_HEADER_SIZE == 32);
+const _: () = assert!(core::mem::offset_of!(AppHeader, header_version) == 0x04);
+const _: () = assert!(core::mem::offset_of!(AppHeader, image_length) == 0x08);
+const _: () = assert!(core::mem::offset_of!(AppHeader, image_crc) == 0x0C);
+const _: () = assert!(core::mem::offset_of!(AppHeader, fw_version) == 0x10);
+const _: () = assert!(core::mem::offset_of!(AppHeader, confirm) == 0x18);
+
+impl AppHeader {
+    /// Header for application to place in its `.app_header` section,
+    /// `image_length` and `image_crc` are left erased for the packer.
+    pub const fn new(fw_version: [u8; 8]) -> Self {
+        Self {
+            magic: APP_HEADER_MAGIC,
+            header_version: APP_HEADER_VERSION,
+            reserved: [0; 3],
+            image_length: [0xFF; 4],
+            image_crc: [0xFF; 4],
+            fw_version,
+            confirm: ERASED_CONFIRM,
+        }
+    }
+
+    /// Header of installed application
+    pub fn read() -> Self {
+        unsafe { ((FLASH_BASE + REMAIN_OFFSET + APP_HEADER_OFFSET) as *const Self).read_volatile() }
+    }
+
+    /// Header out of raw bytes, e.g. read through flash driver
+    pub fn from_bytes(bytes: &[u8; APP_HEADER_SIZE]) -> Self {
+        // every field is byte array, no alignment is needed
+        unsafe { (bytes.as_ptr() as *const Self).read_unaligned() }
+    }
+
+    pub fn image_length(&self) -> usize {
+        u32::from_le_bytes(self.image_length) as usize
+    }
+
+    pub fn image_crc(&self) -> u32 {
+        u32::from_le_bytes(self.image_crc)
+    }
+
+    pub fn is_confirmed(&self) -> bool {
+        self.confirm == APP_CONFIRM_MAGIC
+    }
+
+    /// Confirmation is still possible, `confirm` double-word is not programmed yet
+    pub fn is_confirmable(&self) -> bool {
+        self.confirm == ERASED_CONFIRM
+    }
+}
+
+/// `image_crc` of `image` starting at application start, for packer.
+/// `image` must be longer than the header.
+pub fn compute_image_crc(image: &[u8]) -> u32 {
+    let crc = Crc::<u32>::new(&LAPLUS_CRC);
+    let mut digest = crc.digest();
+    digest.update(&image[..APP_HEADER_OFFSET]);
+    digest.update(&image[APP_HEADER_OFFSET + APP_HEADER_SIZE..]);
+
+    digest.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_crc_skips_header() {
+        let mut image = [0x5Au8; 0x200];
+        let crc = compute_image_crc(&image);
+
+        image[APP_HEADER_OFFSET..APP_HEADER_OFFSET + APP_HEADER_SIZE].fill(0xFF);
+        assert_eq!(compute_image_crc(&image), crc);
+
+        image[APP_HEADER_OFFSET + APP_HEADER_SIZE] ^= 1;
+        assert_ne!(compute_image_crc(&image), crc);
+    }
+}
@@ -0,0 +1,97 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Application side of laplus-boots-rs bootloader.
+//! Layout and wire formats here are shared with the bootloader,
+//! which asserts them at compile time against its own memory.x.
+//!
+//! Application has to keep `MAILBOX_SIZE` bytes on top of RAM away from its
+//! stack, e.g. `_stack_start = ORIGIN(RAM) + LENGTH(RAM) - 16;` in memory.x.
+
+#![no_std]
+
+pub mod header;
+pub mod mailbox;
+
+use crc::Algorithm;
+pub use mailbox::{Handoff, Mailbox, MailboxCommand};
+
+pub const BOOTLOADER_KEY: u32 = 0xB00710AD; // BOOTLOAD
+pub const CRC_POLY_INIT: u32 = 0xA097;
+
+/// CRC32 of bootloader packets, image header and mailbox,
+/// software equivalent of the CRC peripheral setting of bootloader
+pub const LAPLUS_CRC: Algorithm<u32> = Algorithm {
+    width: 32,
+    poly: 0x4C11DB7,
+    init: CRC_POLY_INIT,
+    refin: true,
+    refout: false,
+    xorout: 0x0000,
+    check: 0,
+    residue: 0x0000,
+};
+
+pub const FLASH_BASE: usize = 0x0800_0000;
+/// Flash of STM32G030C8 as embassy-stm32 knows it, bootloader asserts they match
+pub const FLASH_SIZE: usize = 32 * 1024;
+pub const BOOTLOADER_ORIGIN: usize = FLASH_BASE;
+pub const BOOTLOADER_LENGTH: usize = 8 * 1024;
+/// Application start, relative to `FLASH_BASE`
+pub const REMAIN_OFFSET: usize = BOOTLOADER_ORIGIN + BOOTLOADER_LENGTH - FLASH_BASE;
+/// Largest application image
+pub const REMAIN_SIZE: usize = FLASH_SIZE - REMAIN_OFFSET;
+
+pub const RAM_ORIGIN: usize = 0x2000_0000;
+pub const RAM_LENGTH: usize = 8 * 1024;
+pub const MAILBOX_SIZE: usize = 16;
+pub const MAILBOX_ADDRESS: usize = RAM_ORIGIN + RAM_LENGTH - MAILBOX_SIZE;
+
+/// Jump into bootloader with `BOOTLOADER_KEY` in r0.
+/// Kept for old bootloaders, r0 doesn't survive a real reset.
+///
+/// # Safety
+/// Peripherals and interrupts are left as application set them,
+/// bootloader expects them as they're after reset.
+#[cfg(target_arch = "arm")]
+pub unsafe fn jump_to_bootloader() -> ! {
+    core::arch::asm!("mov r0, {0}", in(reg) BOOTLOADER_KEY); // must be insure r0 is keep until bootstrap
+
+    cortex_m::asm::bootload(BOOTLOADER_ORIGIN as *const u32)
+}
+
+/// Request OTA through the mailbox then reset
+#[cfg(target_arch = "arm")]
+pub fn reset_to_bootloader() -> ! {
+    reset_to_bootloader_on_bus(0)
+}
+
+/// Request OTA then reset, bootloader answers to `bus_address` on RS-485 bus.
+/// `0x00` keeps the address derived from serial number.
+#[cfg(target_arch = "arm")]
+pub fn reset_to_bootloader_on_bus(bus_address: u8) -> ! {
+    Mailbox::new(MailboxCommand::EnterOta, bus_address as u32).post();
+
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Confirm trial boot of this image, bootloader programs the confirm mark
+/// on the way back. Nothing happens when it's not a trial boot.
+#[cfg(target_arch = "arm")]
+pub fn confirm_trial_boot(handoff: &Handoff) {
+    if handoff.trial {
+        Mailbox::new(MailboxCommand::Confirm, 0).post();
+
+        cortex_m::peripheral::SCB::sys_reset()
+    }
+}
+
+/// Handoff left by bootloader on jump, call it once early in `main`.
+/// None when application is started by a debugger or an old bootloader.
+#[cfg(target_arch = "arm")]
+pub fn take_handoff() -> Option<Handoff> {
+    Mailbox::take().handoff()
+}
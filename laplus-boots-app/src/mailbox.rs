@@ -0,0 +1,222 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Mailbox on top of RAM, startup code of neither side initializes it.
+//! Writer resets right after `post`, reader `take`s it (read and clear) on boot.
+//! Content after power-on is random, CRC filters it out.
+
+use crc::Crc;
+
+#[cfg(target_arch = "arm")]
+use crate::MAILBOX_ADDRESS;
+use crate::{LAPLUS_CRC, MAILBOX_SIZE};
+
+pub const MAILBOX_MAGIC: u32 = 0x424D_504C; // "LPMB" in little endian
+
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MailboxCommand {
+    /// Stay in bootloader for OTA
+    EnterOta = 0x01,
+    /// Program confirmation mark of installed application then boot it
+    Confirm = 0x03,
+    /// Left by bootloader for application on jump, argument is `Handoff`
+    Handoff = 0x80,
+}
+
+impl MailboxCommand {
+    const fn from_raw(value: u32) -> Option<Self> {
+        match value {
+            0x01 => Some(Self::EnterOta),
+            0x03 => Some(Self::Confirm),
+            0x80 => Some(Self::Handoff),
+            _ => None,
+        }
+    }
+}
+
+/// What bootloader knows and application can't find out after jump
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Handoff {
+    /// RCC CSR reset flags, same as RCC still has until application clears them
+    pub reset_flags: u8,
+    /// Image has header but isn't confirmed yet
+    pub trial: bool,
+}
+
+impl Handoff {
+    pub const RESET_PIN: u8 = 1 << 0;
+    /// Brown-out or power-on reset
+    pub const RESET_POWER: u8 = 1 << 1;
+    pub const RESET_SOFTWARE: u8 = 1 << 2;
+    pub const RESET_INDEPENDENT_WATCHDOG: u8 = 1 << 3;
+    pub const RESET_WINDOW_WATCHDOG: u8 = 1 << 4;
+    pub const RESET_LOW_POWER: u8 = 1 << 5;
+    pub const RESET_OPTION_BYTE_LOADER: u8 = 1 << 6;
+
+    const fn to_argument(self) -> u32 {
+        u32::from_le_bytes([self.reset_flags, self.trial as u8, 0, 0])
+    }
+
+    const fn from_argument(argument: u32) -> Self {
+        let [reset_flags, trial, _, _] = argument.to_le_bytes();
+
+        Self {
+            reset_flags,
+            trial: trial != 0,
+        }
+    }
+}
+
+/// All fields are little endian words, `crc` covers the other three
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Mailbox {
+    pub magic: u32,
+    pub command: u32,
+    pub argument: u32,
+    pub crc: u32,
+}
+
+const _: () = assert!(core::mem::size_of::<Mailbox>() == MAILBOX_SIZE);
+// offsets documented for application written in other languages
+const _: () = assert!(core::mem::offset_of!(Mailbox, command) == 0x04);
+const _: () = assert!(core::mem::offset_of!(Mailbox, argument) == 0x08);
+const _: () = assert!(core::mem::offset_of!(Mailbox, crc) == 0x0C);
+
+impl Mailbox {
+    pub fn new(command: MailboxCommand, argument: u32) -> Self {
+        let mut ret = Self {
+            magic: MAILBOX_MAGIC,
+            command: command as u32,
+            argument,
+            crc: 0,
+        };
+        ret.crc = ret.compute_crc();
+
+        ret
+    }
+
+    pub fn new_handoff(handoff: Handoff) -> Self {
+        Self::new(MailboxCommand::Handoff, handoff.to_argument())
+    }
+
+    fn compute_crc(&self) -> u32 {
+        let mut src = [0u8; 12];
+        src[0..4].copy_from_slice(&self.magic.to_le_bytes());
+        src[4..8].copy_from_slice(&self.command.to_le_bytes());
+        src[8..12].copy_from_slice(&self.argument.to_le_bytes());
+
+        Crc::<u32>::new(&LAPLUS_CRC).checksum(&src)
+    }
+
+    /// Decoded command when magic and CRC are intact
+    pub fn command(&self) -> Option<MailboxCommand> {
+        if self.magic != MAILBOX_MAGIC || self.crc != self.compute_crc() {
+            return None;
+        }
+
+        MailboxCommand::from_raw(self.command)
+    }
+
+    pub fn handoff(&self) -> Option<Handoff> {
+        match self.command() {
+            Some(MailboxCommand::Handoff) => Some(Handoff::from_argument(self.argument)),
+            _ => None,
+        }
+    }
+
+    /// Read mailbox and clear it, so the request is served only once
+    #[cfg(target_arch = "arm")]
+    pub fn take() -> Self {
+        let ptr = MAILBOX_ADDRESS as *mut Self;
+
+        unsafe {
+            let ret = ptr.read_volatile();
+            ptr.write_volatile(Self {
+                magic: 0,
+                command: 0,
+                argument: 0,
+                crc: 0,
+            });
+
+            ret
+        }
+    }
+
+    /// Leave a message for the other side, writer is expected to reset or jump right after
+    #[cfg(target_arch = "arm")]
+    pub fn post(&self) {
+        unsafe { (MAILBOX_ADDRESS as *mut Self).write_volatile(*self) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_survives_round_trip() {
+        for command in [MailboxCommand::EnterOta, MailboxCommand::Confirm] {
+            let mailbox = Mailbox::new(command, 0x1234_5678);
+
+            assert!(mailbox.command() == Some(command));
+            assert!(mailbox.handoff().is_none());
+        }
+    }
+
+    #[test]
+    fn crc_covers_magic_command_and_argument() {
+        let mut src = [0u8; 12];
+        src[0..4].copy_from_slice(b"LPMB");
+        src[4..8].copy_from_slice(&1u32.to_le_bytes());
+        src[8..12].copy_from_slice(&0x42u32.to_le_bytes());
+        let mailbox = Mailbox::new(MailboxCommand::EnterOta, 0x42);
+
+        assert_eq!(mailbox.magic.to_le_bytes(), *b"LPMB");
+        assert_eq!(mailbox.crc, Crc::<u32>::new(&LAPLUS_CRC).checksum(&src));
+    }
+
+    #[test]
+    fn corrupted_mailbox_has_no_command() {
+        let mailbox = Mailbox::new(MailboxCommand::EnterOta, 0);
+
+        for corrupted in [
+            Mailbox {
+                magic: 0,
+                ..mailbox
+            },
+            Mailbox {
+                argument: 1,
+                ..mailbox
+            },
+            Mailbox {
+                crc: !mailbox.crc,
+                ..mailbox
+            },
+        ] {
+            assert!(corrupted.command().is_none());
+        }
+
+        // intact but unknown command, e.g. power-on garbage passing CRC or newer application
+        let mut unknown = Mailbox {
+            command: 0x02,
+            ..mailbox
+        };
+        unknown.crc = unknown.compute_crc();
+        assert!(unknown.command().is_none());
+    }
+
+    #[test]
+    fn handoff_survives_round_trip() {
+        let handoff = Handoff {
+            reset_flags: Handoff::RESET_PIN | Handoff::RESET_SOFTWARE,
+            trial: true,
+        };
+
+        assert!(Mailbox::new_handoff(handoff).handoff() == Some(handoff));
+    }
+}
@@ -11,6 +11,11 @@ MEMORY
   RAM : ORIGIN = 0x20000000, LENGTH = 8K
 }
 
+/*
+ * Last 16 bytes of RAM is the mailbox shared with application,
+ * build.rs places `_stack_start` below it.
+ */
+
 /*
  * Mass-Production usage ELF section
  * ref - https://sourceware.org/binutils/docs/ld/Output-Section-Type.html
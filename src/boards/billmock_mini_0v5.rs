@@ -8,75 +8,138 @@
 //! The code follows on version mini 0.4 schematic
 //! https://github.com/pmnxis/BillMock-HW-RELEASE/blob/master/sch/BillMock-Mini-HW-0v5.pdf
 
-use embassy_stm32::crc::{self, Crc};
+use embassy_stm32::crc::Crc;
 use embassy_stm32::flash::Flash;
-use embassy_stm32::gpio::{Input, Pin, Pull};
+use embassy_stm32::gpio::{Input, Level, Pin, Pull};
+use embassy_stm32::pac::gpio::Gpio;
+#[cfg(not(feature = "host_i2c"))]
+use embassy_stm32::pac::GPIOA;
+#[cfg(not(feature = "host_i2c"))]
 use embassy_stm32::usart::BufferedUart;
+#[cfg(not(feature = "host_i2c"))]
 use embassy_stm32::{bind_interrupts, peripherals};
 
-use super::Hardware;
+#[cfg(feature = "host_i2c")]
+use super::i2c_target::I2cTargetTransport;
+use super::{crc_config, BoardSpec, Hardware};
+#[cfg(not(feature = "host_i2c"))]
+use super::{usart_config, UartTransport, DEFAULT_BAUDRATE};
 
+// PA1 (USART2_DE) and PB6/PB7 (I2C1) below are where the MCU has these functions,
+// how mini 0v5 wires them isn't confirmed against the schematic yet
+#[cfg(feature = "rs485")]
+compile_error!("rs485 is blocked on mini 0v5, transceiver DE pin is not confirmed");
+#[cfg(feature = "host_i2c")]
+compile_error!("host_i2c is blocked on mini 0v5, I2C host pins are not confirmed");
+
+#[cfg(not(feature = "host_i2c"))]
 bind_interrupts!(struct Irqs {
     USART2 => embassy_stm32::usart::BufferedInterruptHandler<peripherals::USART2>; // InterruptHandler
 });
 
-static mut UART_RX_BUF: [u8; 1024] = [0u8; 1024];
+#[cfg(not(feature = "host_i2c"))]
+const UART_RX_BUF_SIZE: usize = 1024;
+
+#[cfg(not(feature = "host_i2c"))]
+static mut UART_RX_BUF: [u8; UART_RX_BUF_SIZE] = [0u8; UART_RX_BUF_SIZE];
+#[cfg(not(feature = "host_i2c"))]
 static mut UART_TX_BUF: [u8; 512] = [0u8; 512];
 
-pub fn hardware_specific_init<'s>(p: embassy_stm32::Peripherals) -> Hardware<'s> {
-    let delay = cortex_m::delay::Delay::new(
-        unsafe { cortex_m::Peripherals::steal().SYST },
-        embassy_stm32::rcc::HSI_FREQ.0,
-    );
-
-    // USART2 initialization for CardReaderDevice
-    let usart_rx_buf = unsafe { &mut *core::ptr::addr_of_mut!(UART_RX_BUF) };
-    let usart_tx_buf = unsafe { &mut *core::ptr::addr_of_mut!(UART_TX_BUF) };
-
-    let crc_config = crc::Config::new(
-        crc::InputReverseConfig::Word,
-        false,
-        crate::types::CRC_POLY_INIT,
-    )
-    .unwrap_or_else(|_| panic!());
-
-    let usart2_config = {
-        let mut ret = embassy_stm32::usart::Config::default();
-        ret.baudrate = 115200;
-        ret.assume_noise_free = false;
-        ret.detect_previous_overrun = true;
-        ret
-    };
-
-    let (tx, rx) = BufferedUart::new(
-        p.USART2,
-        Irqs,
-        p.PA3,
-        p.PA2,
-        usart_tx_buf,
-        usart_rx_buf,
-        usart2_config,
-    )
-    .unwrap_or_else(|_| panic!())
-    .split();
-
-    let force_bootloader = Input::new(p.PC6.degrade(), Pull::Up);
-
-    Hardware {
-        delay,
-        crc: Crc::new(p.CRC, crc_config),
-        flash: Flash::new_blocking(p.FLASH).into_blocking_regions(),
-        rx,
-        tx,
-        force_bootloader,
+pub struct BillMockMini0v5;
+
+impl BoardSpec for BillMockMini0v5 {
+    #[cfg(not(feature = "host_i2c"))]
+    type HostTransport<'s> = UartTransport<'s, peripherals::USART2>;
+    #[cfg(feature = "host_i2c")]
+    type HostTransport<'s> = I2cTargetTransport;
+
+    #[cfg(not(feature = "host_i2c"))]
+    const UART_RX_BUF_SIZE: usize = UART_RX_BUF_SIZE;
+    /// Host is held by clock stretching while flash is busy
+    #[cfg(feature = "host_i2c")]
+    const UART_RX_BUF_SIZE: usize = 0;
+    const ENTRY_ACTIVE_LEVEL: Level = Level::Low;
+    /// No LED is driven until its pin is confirmed, level is a placeholder
+    const LED_ACTIVE_LEVEL: Level = Level::High;
+    /// USART2_RX
+    #[cfg(not(feature = "host_i2c"))]
+    const HOST_RX_PIN: Option<(Gpio, usize)> = Some((GPIOA, 3));
+    #[cfg(feature = "host_i2c")]
+    const HOST_RX_PIN: Option<(Gpio, usize)> = None;
+    #[cfg(feature = "host_i2c")]
+    const I2C_TARGET_ADDRESS: u8 = 0x42;
+
+    fn hardware_specific_init<'s>(
+        p: embassy_stm32::Peripherals,
+    ) -> (Hardware<'s>, Self::HostTransport<'s>) {
+        let delay = cortex_m::delay::Delay::new(
+            unsafe { cortex_m::Peripherals::steal().SYST },
+            embassy_stm32::rcc::HSI_FREQ.0,
+        );
+
+        #[cfg(not(feature = "host_i2c"))]
+        let transport = {
+            // USART2 initialization for CardReaderDevice
+            let usart_rx_buf = unsafe { &mut *core::ptr::addr_of_mut!(UART_RX_BUF) };
+            let usart_tx_buf = unsafe { &mut *core::ptr::addr_of_mut!(UART_TX_BUF) };
+
+            let usart2_config = usart_config(DEFAULT_BAUDRATE);
+
+            #[cfg(not(feature = "rs485"))]
+            let uart = BufferedUart::new(
+                p.USART2,
+                Irqs,
+                p.PA3,
+                p.PA2,
+                usart_tx_buf,
+                usart_rx_buf,
+                usart2_config,
+            );
+
+            // RS-485 transceiver driver is enabled by PA1 (USART2_DE) while transmitting
+            #[cfg(feature = "rs485")]
+            let uart = BufferedUart::new_with_de(
+                p.USART2,
+                Irqs,
+                p.PA3,
+                p.PA2,
+                p.PA1,
+                usart_tx_buf,
+                usart_rx_buf,
+                usart2_config,
+            );
+
+            let (tx, rx) = uart.unwrap_or_else(|_| panic!()).split();
+
+            UartTransport { tx, rx }
+        };
+
+        #[cfg(feature = "host_i2c")]
+        // I2C1 on PB6 (SCL) and PB7 (SDA)
+        let transport = I2cTargetTransport::new(p.I2C1, p.PB6, p.PB7, Self::I2C_TARGET_ADDRESS);
+
+        let force_bootloader = Input::new(p.PC6.degrade(), Pull::Up);
+
+        (
+            Hardware {
+                delay,
+                crc: Crc::new(p.CRC, crc_config()),
+                flash: Flash::new_blocking(p.FLASH).into_blocking_regions(),
+                force_bootloader,
+                // LED pins and level aren't confirmed against the schematic yet, nothing to drive
+                status_led: None,
+                error_led: None,
+            },
+            transport,
+        )
     }
-}
 
-pub(crate) fn serial_number() -> [u8; 12] {
-    billmock_otp_dev_info::OtpDeviceInfo::from_stm32g0().dev_sn
-}
+    fn serial_number() -> [u8; 12] {
+        billmock_otp_dev_info::OtpDeviceInfo::from_stm32g0().dev_sn
+    }
 
-pub(crate) fn crypto_nonce() -> [u8; 12] {
-    // Use RNG when HW support it
-    serial_number()
+    fn crypto_nonce() -> [u8; 12] {
+        // Use RNG when HW support it
+        Self::serial_number()
+    }
 }
@@ -4,12 +4,19 @@
  * SPDX-License-Identifier: MIT OR Apache-2.0
  */
 
+#[cfg(target_arch = "arm")]
 use billmock_otp_dev_info::OtpDeviceInfo;
 use env_to_array::hex_env_to_array;
+use static_assertions::const_assert;
 
 pub const PROJECT_NAME: &str = env!("PROJECT_NAME");
 
-pub const VERSION_STR: [u8; 5/* card_terminal_adapter::FW_VER_LEN */] =
+pub const FW_VER_LEN: usize = 5;
+pub const PROJECT_NAME_LEN: usize = 16;
+pub const GIT_DATETIME_LEN: usize = 25; // "YYYY-MM-DD HH:MM:SS +ZZZZ"
+pub const HW_MODEL_LEN: usize = 24;
+
+pub const VERSION_STR: [u8; FW_VER_LEN/* card_terminal_adapter::FW_VER_LEN */] =
     hex_env_to_array!("PROJECT_VERSION");
 
 pub const COMMIT_HASH: &str = env!("GIT_COMMIT_HASH");
@@ -22,8 +29,32 @@ pub(crate) const COMMIT_SHORT: [u8; GIT_HASH_LEN] = hex_env_to_array!("GIT_COMMI
 pub const SERIAL_NUMBER_WHEN_UNKNOWN: [u8; DEV_SN_LEN] = *b"     unknown";
 
 pub const GIT_COMMIT_DATETIME: &str = env!("GIT_COMMIT_DATETIME");
+
+/// Model name from `hw_*` feature, e.g. `BILLMOCK-MINI-0V5`
+pub const HW_MODEL: &str = env!("HW_MODEL");
+// `str_to_array` truncates, model name must fit as is
+const_assert!(HW_MODEL.len() <= HW_MODEL_LEN);
+
+pub const PROJECT_NAME_ARRAY: [u8; PROJECT_NAME_LEN] = str_to_array(PROJECT_NAME);
+pub const GIT_COMMIT_DATETIME_ARRAY: [u8; GIT_DATETIME_LEN] = str_to_array(GIT_COMMIT_DATETIME);
+pub const HW_MODEL_ARRAY: [u8; HW_MODEL_LEN] = str_to_array(HW_MODEL);
 pub const PRINT_BAR: &str = "+-----------------------------------------------------------+";
 
+/// Copy string into fixed size array, truncated or padded with zero
+const fn str_to_array<const N: usize>(s: &str) -> [u8; N] {
+    let bytes = s.as_bytes();
+    let mut ret = [0u8; N];
+    let mut i = 0;
+
+    while i < N && i < bytes.len() {
+        ret[i] = bytes[i];
+        i += 1;
+    }
+
+    ret
+}
+
+#[cfg(target_arch = "arm")]
 pub fn get_serial_number() -> &'static [u8; DEV_SN_LEN] {
     let otp_space = OtpDeviceInfo::from_stm32g0();
 
@@ -0,0 +1,254 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Host stand-ins of MCU peripherals and host link for unit tests.
+//! Flash is a RAM copy behaving as STM32G0 flash does, CRC is software one
+//! with the same parameters as the peripheral.
+
+use core::convert::Infallible;
+
+use crc::Crc;
+use embedded_io::{ErrorType, Read, Write};
+use laplus_boots_app::{CRC_POLY_INIT, LAPLUS_CRC};
+
+use super::{is_baudrate_reachable, Board, Checksum, Device, OtaFlash, DEFAULT_BAUDRATE};
+use crate::service::Transport;
+use crate::types::boot_reason::{BootDiagnostics, BootReason, ResetFlags};
+use crate::types::indicator::Indicator;
+use crate::types::option_bytes::OptionBytes;
+use crate::types::ota::OtaError;
+use crate::types::region::{PAGE_SIZE, WRITE_SIZE};
+use crate::types::section_mark::FLASH_SIZE;
+
+static CRC: Crc<u32> = Crc::<u32>::new(&LAPLUS_CRC);
+
+pub const SERIAL_NUMBER: [u8; 12] = *b"FAKE-0000001";
+
+/// Clock of USART on target, MCU runs on HSI
+const HSI_FREQ: u32 = 16_000_000;
+
+/// Software CRC, keeps feeding from the last result as CRC peripheral does
+pub struct SoftCrc {
+    state: u32,
+}
+
+impl Checksum for SoftCrc {
+    fn reset(&mut self) {
+        self.state = CRC_POLY_INIT;
+    }
+
+    fn feed_bytes(&mut self, bytes: &[u8]) -> u32 {
+        let mut digest = CRC.digest_with_initial(self.state);
+        digest.update(bytes);
+        self.state = digest.finalize();
+
+        self.state
+    }
+}
+
+/// Whole flash from `FLASH_BASE`, starts erased
+pub struct FakeFlash {
+    pub memory: Vec<u8>,
+}
+
+impl FakeFlash {
+    fn range(&self, offset: u32, len: usize) -> Result<core::ops::Range<usize>, OtaError> {
+        let start = offset as usize;
+
+        match start.checked_add(len) {
+            Some(end) if end <= self.memory.len() => Ok(start..end),
+            _ => Err(OtaError::FlashSize),
+        }
+    }
+}
+
+impl OtaFlash for FakeFlash {
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), OtaError> {
+        let range = self.range(offset, bytes.len())?;
+        bytes.copy_from_slice(&self.memory[range]);
+
+        Ok(())
+    }
+
+    /// Double-word can be programmed only once after erase, as on STM32G0
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), OtaError> {
+        let range = self.range(offset, bytes.len())?;
+        if offset as usize % WRITE_SIZE != 0 || bytes.len() % WRITE_SIZE != 0 {
+            return Err(OtaError::FlashUnaligned);
+        }
+        if self.memory[range.clone()].iter().any(|&x| x != 0xFF) {
+            return Err(OtaError::FlashProg);
+        }
+        self.memory[range].copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), OtaError> {
+        let range = self.range(from, to.saturating_sub(from) as usize)?;
+        if range.start % PAGE_SIZE != 0 || range.end % PAGE_SIZE != 0 {
+            return Err(OtaError::FlashUnaligned);
+        }
+        self.memory[range].fill(0xFF);
+
+        Ok(())
+    }
+}
+
+pub struct FakeHardware {
+    pub crc: SoftCrc,
+    pub flash: FakeFlash,
+    pub option_bytes: OptionBytes,
+    /// `PROTECT_FLAG_*` programmed so far
+    pub programmed_protection: u8,
+    /// Last indicator shown
+    pub indicator: Option<Indicator>,
+}
+
+impl FakeHardware {
+    pub fn new() -> Self {
+        Self {
+            crc: SoftCrc {
+                state: CRC_POLY_INIT,
+            },
+            flash: FakeFlash {
+                memory: vec![0xFF; FLASH_SIZE],
+            },
+            // RDP level 0 without write protection, as shipped
+            option_bytes: OptionBytes {
+                optr: 0xFFFF_FEAA,
+                wrp1ar: 0x0000_003F,
+                wrp1br: 0x0000_003F,
+            },
+            programmed_protection: 0,
+            indicator: None,
+        }
+    }
+}
+
+impl Device for FakeHardware {
+    type Crc = SoftCrc;
+    type Flash = FakeFlash;
+
+    const UART_RX_BUF_SIZE: usize = 1024;
+
+    fn crc(&mut self) -> &mut Self::Crc {
+        &mut self.crc
+    }
+
+    fn flash(&mut self) -> &mut Self::Flash {
+        &mut self.flash
+    }
+
+    fn serial_number(&self) -> [u8; 12] {
+        SERIAL_NUMBER
+    }
+
+    fn crypto_nonce(&self) -> [u8; 12] {
+        SERIAL_NUMBER
+    }
+
+    fn option_bytes(&self) -> OptionBytes {
+        self.option_bytes
+    }
+
+    fn program_option_bytes(&mut self, flags: u8) -> Result<(), OtaError> {
+        self.programmed_protection |= flags;
+
+        Ok(())
+    }
+
+    fn show_indicator(&mut self, indicator: Indicator) {
+        self.indicator = Some(indicator);
+    }
+
+    fn reset(&mut self) -> ! {
+        panic!("reset")
+    }
+
+    fn jump_to_app(&mut self) -> ! {
+        panic!("jump to application")
+    }
+
+    fn reload_option_bytes(&mut self) -> ! {
+        panic!("reload option bytes")
+    }
+}
+
+/// Board as if host asked OTA entry through the entry pin
+pub fn fake_board() -> Board<FakeHardware> {
+    Board::new(
+        FakeHardware::new(),
+        BootDiagnostics {
+            reason: BootReason::ForcePin,
+            reset_flags: ResetFlags(ResetFlags::PIN),
+            boot_param: 0,
+            mailbox_command: 0,
+        },
+    )
+}
+
+/// Host link out of byte buffers, `rx` is what host sent and `tx` is what it received
+pub struct FakeTransport {
+    pub rx: Vec<u8>,
+    pub tx: Vec<u8>,
+    /// Line speed as UART would be set
+    pub baudrate: u32,
+}
+
+impl FakeTransport {
+    pub fn new(rx: &[u8]) -> Self {
+        Self {
+            rx: rx.to_vec(),
+            tx: Vec::new(),
+            baudrate: DEFAULT_BAUDRATE,
+        }
+    }
+}
+
+impl ErrorType for FakeTransport {
+    type Error = Infallible;
+}
+
+impl Read for FakeTransport {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let n = buf.len().min(self.rx.len());
+        buf[..n].copy_from_slice(&self.rx[..n]);
+        self.rx.drain(..n);
+
+        Ok(n)
+    }
+}
+
+impl Write for FakeTransport {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.tx.extend_from_slice(buf);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// UART clocked with HSI as on target
+impl Transport for FakeTransport {
+    fn set_baudrate(&mut self, baudrate: u32) -> Result<(), OtaError> {
+        self.check_baudrate(baudrate)?;
+        self.baudrate = baudrate;
+
+        Ok(())
+    }
+
+    fn check_baudrate(&self, baudrate: u32) -> Result<(), OtaError> {
+        if is_baudrate_reachable(HSI_FREQ, baudrate) {
+            Ok(())
+        } else {
+            Err(OtaError::InvalidArgument)
+        }
+    }
+}
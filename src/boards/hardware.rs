@@ -0,0 +1,300 @@
+/*
+ * SPDX-FileCopyrightText: © 2023 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! MCU side of [`Device`], only built for the target.
+//! Each `boards::*` module fills [`Hardware`] through [`BoardSpec`].
+
+use core::task::Poll;
+
+use embassy_stm32::crc::Crc;
+use embassy_stm32::flash::{Blocking, FlashLayout};
+use embassy_stm32::gpio::{AnyPin, Input, Level, Output};
+use embassy_stm32::pac::gpio::vals::{Idr, Pupdr};
+use embassy_stm32::pac::gpio::Gpio;
+use embassy_stm32::usart::{BasicInstance, BufferedUartRx, BufferedUartTx};
+use embedded_io::{ErrorType, Read, Write};
+
+use super::{is_baudrate_reachable, Checksum, Device, OtaFlash};
+use crate::service::Transport;
+use crate::types::indicator::Indicator;
+use crate::types::option_bytes::{self, OptionBytes};
+use crate::types::ota::OtaError;
+
+#[cfg(feature = "hw_billmock_mini_0v5")]
+pub type CurrentBoard = super::billmock_mini_0v5::BillMockMini0v5;
+
+/// Link to host on the selected board
+pub type HostTransport<'s> = <CurrentBoard as BoardSpec>::HostTransport<'s>;
+
+/// What differs between hardware revisions, each `boards::*` module implements it
+/// and exactly one of them is selected by `hw_*` feature.
+pub trait BoardSpec {
+    /// Link to host, UART or I2C target depending on board
+    type HostTransport<'s>: Transport;
+
+    /// Bytes host can send ahead while bootloader is busy with flash,
+    /// 0 when the transport holds host back by itself (e.g. I2C clock stretching)
+    const UART_RX_BUF_SIZE: usize;
+    /// Level of `force_bootloader` pin asking to stay in bootloader
+    const ENTRY_ACTIVE_LEVEL: Level;
+    /// Level of `status_led` and `error_led` pins turning the LED on
+    const LED_ACTIVE_LEVEL: Level;
+    /// Port and number of host UART RX pin, sampled for break.
+    /// `None` on transport without RX line, e.g. I2C.
+    const HOST_RX_PIN: Option<(Gpio, usize)>;
+    /// 7-bit address answered to host MCU on I2C
+    #[cfg(feature = "host_i2c")]
+    const I2C_TARGET_ADDRESS: u8;
+
+    /// Initialize host link, entry pin and LEDs out of MCU peripherals
+    fn hardware_specific_init<'s>(
+        p: embassy_stm32::Peripherals,
+    ) -> (Hardware<'s>, Self::HostTransport<'s>);
+
+    fn serial_number() -> [u8; 12];
+
+    fn crypto_nonce() -> [u8; 12];
+}
+
+/// Host UART configuration, also used when host switches baudrate at runtime
+#[cfg_attr(feature = "host_i2c", allow(dead_code))]
+pub(crate) fn usart_config(baudrate: u32) -> embassy_stm32::usart::Config {
+    let mut ret = embassy_stm32::usart::Config::default();
+    ret.baudrate = baudrate;
+    ret.assume_noise_free = false;
+    ret.detect_previous_overrun = true;
+    ret
+}
+
+/// CRC peripheral configuration shared by every board, same as `std_crc`
+pub(crate) fn crc_config() -> embassy_stm32::crc::Config {
+    embassy_stm32::crc::Config::new(
+        embassy_stm32::crc::InputReverseConfig::Word,
+        false,
+        crate::types::CRC_POLY_INIT,
+    )
+    .unwrap_or_else(|_| panic!())
+}
+
+impl From<embassy_stm32::flash::Error> for OtaError {
+    fn from(value: embassy_stm32::flash::Error) -> Self {
+        match value {
+            embassy_stm32::flash::Error::Prog => Self::FlashProg,
+            embassy_stm32::flash::Error::Size => Self::FlashSize,
+            embassy_stm32::flash::Error::Miss => Self::FlashMiss,
+            embassy_stm32::flash::Error::Seq => Self::FlashSeq,
+            embassy_stm32::flash::Error::Protected => Self::FlashProtected,
+            embassy_stm32::flash::Error::Unaligned => Self::FlashUnaligned,
+            embassy_stm32::flash::Error::Parallelism => Self::FlashParallelism,
+        }
+    }
+}
+
+impl Checksum for Crc<'_> {
+    fn reset(&mut self) {
+        Crc::reset(self)
+    }
+
+    fn feed_bytes(&mut self, bytes: &[u8]) -> u32 {
+        Crc::feed_bytes(self, bytes)
+    }
+}
+
+impl OtaFlash for FlashLayout<'_, Blocking> {
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), OtaError> {
+        Ok(self.bank1_region.blocking_read(offset, bytes)?)
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), OtaError> {
+        Ok(self.bank1_region.blocking_write(offset, bytes)?)
+    }
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), OtaError> {
+        Ok(self.bank1_region.blocking_erase(from, to)?)
+    }
+}
+
+#[allow(dead_code)]
+pub struct Hardware<'s> {
+    pub delay: cortex_m::delay::Delay,
+    pub crc: Crc<'s>,
+    pub flash: FlashLayout<'s, Blocking>,
+    pub force_bootloader: Input<'s, AnyPin>,
+    /// `None` on boards without LED
+    pub status_led: Option<Output<'s, AnyPin>>,
+    /// Second LED for failures, `None` on boards with single LED
+    pub error_led: Option<Output<'s, AnyPin>>,
+}
+
+impl Hardware<'_> {
+    /// Initialize MCU PLL and CPU on init hardware
+    pub fn mcu_pre_init() -> embassy_stm32::Peripherals {
+        embassy_stm32::init(Default::default())
+    }
+
+    /// Initialize MCU peripherals and nearby components
+    #[inline]
+    fn hardware_init<'s>(
+        peripherals: embassy_stm32::Peripherals,
+    ) -> (Hardware<'s>, HostTransport<'s>) {
+        let ret = CurrentBoard::hardware_specific_init(peripherals);
+
+        // RX floats without host, it must not read as break
+        if let Some((port, pin)) = CurrentBoard::HOST_RX_PIN {
+            port.pupdr().modify(|w| w.set_pupdr(pin, Pupdr::PULLUP));
+        }
+
+        ret
+    }
+
+    /// `force_bootloader` pin is at the level asking to stay in bootloader
+    pub fn is_force_bootloader_active(&self) -> bool {
+        self.force_bootloader.get_level() == CurrentBoard::ENTRY_ACTIVE_LEVEL
+    }
+
+    /// Host holds RX line low (break), idle UART line is high.
+    /// RX pin level is readable while it's in alternate function mode.
+    pub fn is_host_rx_break(&self) -> bool {
+        match CurrentBoard::HOST_RX_PIN {
+            Some((port, pin)) => port.idr().read().idr(pin) == Idr::LOW,
+            None => false,
+        }
+    }
+
+    pub fn set_status_led(&mut self, on: bool) {
+        if let Some(led) = self.status_led.as_mut() {
+            led.set_level(led_level(on));
+        }
+    }
+
+    pub fn set_error_led(&mut self, on: bool) {
+        if let Some(led) = self.error_led.as_mut() {
+            led.set_level(led_level(on));
+        }
+    }
+}
+
+impl<'s> Device for Hardware<'s> {
+    type Crc = Crc<'s>;
+    type Flash = FlashLayout<'s, Blocking>;
+
+    const UART_RX_BUF_SIZE: usize = CurrentBoard::UART_RX_BUF_SIZE;
+
+    fn crc(&mut self) -> &mut Self::Crc {
+        &mut self.crc
+    }
+
+    fn flash(&mut self) -> &mut Self::Flash {
+        &mut self.flash
+    }
+
+    fn serial_number(&self) -> [u8; 12] {
+        CurrentBoard::serial_number()
+    }
+
+    fn crypto_nonce(&self) -> [u8; 12] {
+        CurrentBoard::crypto_nonce()
+    }
+
+    fn option_bytes(&self) -> OptionBytes {
+        OptionBytes::read()
+    }
+
+    fn program_option_bytes(&mut self, flags: u8) -> Result<(), OtaError> {
+        option_bytes::program(flags)
+    }
+
+    fn show_indicator(&mut self, indicator: Indicator) {
+        let ms = embassy_time::Instant::now().as_millis();
+        let (status, error) = indicator.levels(ms, self.error_led.is_some());
+
+        self.set_status_led(status);
+        self.set_error_led(error);
+    }
+
+    fn reset(&mut self) -> ! {
+        cortex_m::peripheral::SCB::sys_reset()
+    }
+
+    fn jump_to_app(&mut self) -> ! {
+        unsafe { crate::types::jump_to_app() }
+    }
+
+    fn reload_option_bytes(&mut self) -> ! {
+        option_bytes::reload()
+    }
+}
+
+/// Pin level turning LED on or off, same active level for both LEDs
+fn led_level(on: bool) -> Level {
+    match (on, CurrentBoard::LED_ACTIVE_LEVEL) {
+        (true, level) => level,
+        (false, Level::High) => Level::Low,
+        (false, Level::Low) => Level::High,
+    }
+}
+
+/// Host UART of the board as OTA transport
+#[cfg_attr(feature = "host_i2c", allow(dead_code))]
+pub struct UartTransport<'s, T: BasicInstance> {
+    pub tx: BufferedUartTx<'s, T>,
+    pub rx: BufferedUartRx<'s, T>,
+}
+
+impl<T: BasicInstance> ErrorType for UartTransport<'_, T> {
+    type Error = embassy_stm32::usart::Error;
+}
+
+impl<T: BasicInstance> Read for UartTransport<'_, T> {
+    /// Never blocks, so LEDs keep blinking while host is silent
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        // async read is ready at once when RX buffer has data, pending otherwise
+        match embassy_futures::poll_once(embedded_io_async::Read::read(&mut self.rx, buf)) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Ok(0),
+        }
+    }
+}
+
+impl<T: BasicInstance> Write for UartTransport<'_, T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.tx.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.tx.flush()
+    }
+}
+
+impl<T: BasicInstance> Transport for UartTransport<'_, T> {
+    fn set_baudrate(&mut self, baudrate: u32) -> Result<(), OtaError> {
+        let _ = self.tx.flush();
+        // flush only waits TX buffer empty, give shift register time for last byte
+        embassy_time::block_for(embassy_time::Duration::from_millis(1));
+
+        self.rx
+            .set_config(&usart_config(baudrate))
+            .map_err(|_| OtaError::InvalidArgument)
+    }
+
+    /// USART is clocked by PCLK, which is HSI as `mcu_pre_init` leaves it
+    fn check_baudrate(&self, baudrate: u32) -> Result<(), OtaError> {
+        if is_baudrate_reachable(embassy_stm32::rcc::HSI_FREQ.0, baudrate) {
+            Ok(())
+        } else {
+            Err(OtaError::InvalidArgument)
+        }
+    }
+}
+
+impl Hardware<'static> {
+    /// Host link is returned aside, so OTA service can borrow both at once
+    pub fn init() -> (Self, HostTransport<'static>) {
+        let peripherals = Self::mcu_pre_init();
+
+        Self::hardware_init(peripherals)
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! I2C1 target (slave) transport for boards wired to host MCU by I2C.
+//! embassy-stm32 0.1.0 has no target mode, so I2C1 is driven through PAC
+//! registers by polling, clock is stretched while the bootloader is busy.
+//!
+//! Host writes a request frame to `address`, then reads the response.
+//! Reading before response is ready or beyond its end gives `0x00`,
+//! so host polls until SOF byte appears.
+
+use embassy_stm32::gpio::Pin;
+use embassy_stm32::i2c::{SclPin, SdaPin};
+use embassy_stm32::pac::gpio::vals::{Moder, Ot, Pupdr};
+use embassy_stm32::pac::i2c::vals::Dir;
+use embassy_stm32::pac::{self, I2C1, RCC};
+use embassy_stm32::peripherals;
+use embassy_time::{Duration, Instant};
+use embedded_io::{ErrorType, Read, Write};
+
+use crate::service::Transport;
+use crate::types::ota::REASONABLE_TX_BUF;
+
+/// SCLDEL/SDADEL for 16MHz kernel clock up to 400kHz, others are only for controller mode
+const TIMINGR_16MHZ: u32 = 0x0010_061A;
+/// Give up transmitting response when host doesn't read it in time
+const WAIT_DURATION_TX: Duration = Duration::from_millis(100);
+/// Sent when host reads with nothing to give
+const IDLE_BYTE: u8 = 0x00;
+
+pub struct I2cTargetTransport {
+    tx_buf: [u8; REASONABLE_TX_BUF],
+    tx_len: usize,
+    /// bytes shifted out to host so far
+    tx_pos: usize,
+    /// `tx_buf[tx_pos]` is written on TXDR but not shifted out yet
+    tx_loaded: bool,
+}
+
+impl I2cTargetTransport {
+    /// Take SCL and SDA pins of the board, answer to 7-bit `address`
+    pub fn new(
+        _i2c: peripherals::I2C1,
+        scl: impl SclPin<peripherals::I2C1>,
+        sda: impl SdaPin<peripherals::I2C1>,
+        address: u8,
+    ) -> Self {
+        RCC.apbenr1().modify(|w| w.set_i2c1en(true));
+
+        for (af, pin) in [(scl.af_num(), scl.degrade()), (sda.af_num(), sda.degrade())] {
+            let (port, n) = (pin.block(), pin.pin() as usize);
+
+            // GPIOxEN bits are in order of port
+            RCC.gpioenr().modify(|w| w.0 |= 1 << pin.port());
+            port.otyper().modify(|w| w.set_ot(n, Ot::OPENDRAIN));
+            port.pupdr().modify(|w| w.set_pupdr(n, Pupdr::PULLUP));
+            port.afr(n / 8).modify(|w| w.set_afr(n % 8, af));
+            port.moder().modify(|w| w.set_moder(n, Moder::ALTERNATE));
+        }
+
+        I2C1.cr1().modify(|w| w.set_pe(false));
+        I2C1.timingr()
+            .write_value(pac::i2c::regs::Timingr(TIMINGR_16MHZ));
+        I2C1.oar1().write(|w| {
+            w.set_oa1(((address & 0x7F) as u16) << 1);
+            w.set_oa1en(true);
+        });
+        I2C1.cr1().modify(|w| w.set_pe(true));
+
+        Self {
+            tx_buf: [0; REASONABLE_TX_BUF],
+            tx_len: 0,
+            tx_pos: 0,
+            tx_loaded: false,
+        }
+    }
+
+    /// Serve pending I2C events once, received byte is stored on `rx`
+    /// when there's room. Returns whether anything has happened.
+    fn poll(&mut self, rx: &mut [u8], rx_len: &mut usize) -> bool {
+        let isr = I2C1.isr().read();
+
+        if isr.addr() {
+            if isr.dir() == Dir::READ {
+                // host reads, drop stale byte in TXDR
+                I2C1.isr().write(|w| w.set_txe(true));
+                self.tx_loaded = false;
+            }
+            I2C1.icr().write(|w| w.set_addrcf(true));
+            true
+        } else if isr.rxne() {
+            if *rx_len >= rx.len() {
+                // keep clock stretched until next read call
+                return false;
+            }
+            rx[*rx_len] = I2C1.rxdr().read().rxdata();
+            *rx_len += 1;
+            true
+        } else if isr.txis() {
+            // TXDR is empty again, the byte loaded last time is shifted out
+            if self.tx_loaded {
+                self.tx_pos += 1;
+            }
+            self.tx_loaded = self.tx_pos < self.tx_len;
+
+            let byte = if self.tx_loaded {
+                self.tx_buf[self.tx_pos]
+            } else {
+                IDLE_BYTE
+            };
+            I2C1.txdr().write(|w| w.set_txdata(byte));
+            true
+        } else if isr.nackf() || isr.stopf() {
+            // host stopped reading, the byte still in TXDR is sent again next time
+            if isr.nackf() {
+                if isr.txe() {
+                    // shifted out right before NACK
+                    self.tx_pos += self.tx_loaded as usize;
+                }
+                self.tx_loaded = false;
+                I2C1.isr().write(|w| w.set_txe(true));
+            }
+            I2C1.icr().write(|w| {
+                w.set_nackcf(true);
+                w.set_stopcf(true);
+            });
+            true
+        } else {
+            false
+        }
+    }
+
+    fn tx_pending(&self) -> bool {
+        self.tx_pos < self.tx_len
+    }
+}
+
+impl ErrorType for I2cTargetTransport {
+    type Error = core::convert::Infallible;
+}
+
+impl Read for I2cTargetTransport {
+    /// Never blocks, `Ok(0)` when host hasn't written anything
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut rx_len = 0;
+        while self.poll(buf, &mut rx_len) {}
+
+        Ok(rx_len)
+    }
+}
+
+impl Write for I2cTargetTransport {
+    /// Queue response, host takes it on next read transaction
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if !self.tx_pending() {
+            self.tx_len = 0;
+            self.tx_pos = 0;
+            self.tx_loaded = false;
+        }
+
+        let n = buf.len().min(self.tx_buf.len() - self.tx_len);
+        self.tx_buf[self.tx_len..self.tx_len + n].copy_from_slice(&buf[..n]);
+        self.tx_len += n;
+
+        Ok(n)
+    }
+
+    /// Wait until host read the whole response or gave up reading,
+    /// request written meanwhile is dropped
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        let deadline = Instant::now() + WAIT_DURATION_TX;
+        let mut discard = [0u8; 1];
+
+        while self.tx_pending() && Instant::now() < deadline {
+            let mut discard_len = 0;
+            self.poll(&mut discard, &mut discard_len);
+        }
+        self.tx_pos = self.tx_len;
+        self.tx_loaded = false;
+
+        Ok(())
+    }
+}
+
+impl Transport for I2cTargetTransport {}
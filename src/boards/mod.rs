@@ -5,91 +5,166 @@
  */
 
 use chacha20::{cipher::KeyIvInit, ChaCha20};
-use embassy_stm32::crc::Crc;
-use embassy_stm32::flash::FlashLayout;
-use embassy_stm32::gpio::{AnyPin, Input};
-use embassy_stm32::peripherals;
-use embassy_stm32::usart::{BufferedUartRx, BufferedUartTx};
-
-// #[cfg(feature = "hw_0v2")]
-// use self::billmock_0v2::hardware_init_0v2;
-#[cfg(feature = "hw_billmock_mini_0v5")]
-use self::billmock_mini_0v5::*;
+
+use crate::types::boot_reason::BootDiagnostics;
+use crate::types::frame::derive_bus_address;
+use crate::types::indicator::Indicator;
+use crate::types::option_bytes::OptionBytes;
+use crate::types::ota::OtaError;
 use crate::types::section_mark::SectionMark;
+use crate::types::write_window::WriteWindow;
 
-// #[cfg(feature = "hw_0v2")]
-// mod billmock_0v2;
-#[cfg(feature = "hw_billmock_mini_0v5")]
+#[cfg(all(target_arch = "arm", feature = "hw_billmock_mini_0v5"))]
 mod billmock_mini_0v5;
+#[cfg(test)]
+pub mod fake;
+#[cfg(target_arch = "arm")]
+mod hardware;
+#[cfg(all(target_arch = "arm", feature = "host_i2c"))]
+mod i2c_target;
+
+#[cfg(target_arch = "arm")]
+pub use hardware::*;
+
+#[cfg(feature = "hw_0v2")]
+compile_error!("hw_0v2 is blocked, BillMock 0v2 pin map is not confirmed against its schematic");
+
+#[cfg(all(feature = "host_i2c", feature = "rs485"))]
+compile_error!("RS-485 is a UART mode, it cannot be used with I2C host link");
 
 #[allow(dead_code)]
 pub mod const_str;
 
-#[allow(dead_code)]
-pub struct Hardware<'s> {
-    pub delay: cortex_m::delay::Delay,
-    pub crc: Crc<'s>,
-    pub flash: FlashLayout<'s, embassy_stm32::flash::Blocking>,
-    pub tx: BufferedUartTx<'s, peripherals::USART2>,
-    pub rx: BufferedUartRx<'s, peripherals::USART2>,
-    pub force_bootloader: Input<'s, AnyPin>,
-}
+/// Baudrate on power-up, host may raise it with `SetBaudRate` command
+pub const DEFAULT_BAUDRATE: u32 = 115200;
 
-impl Hardware<'_> {
-    /// Initialize MCU PLL and CPU on init hardware
-    pub fn mcu_pre_init() -> embassy_stm32::Peripherals {
-        embassy_stm32::init(Default::default())
-    }
+/// Most a UART baudrate may be off, both ends together must stay within about 4%
+const BAUDRATE_TOLERANCE_PERMILLE: u64 = 20;
 
-    /// Initialize MCU peripherals and nearby components
-    #[inline]
-    fn hardware_init<'s>(peripherals: embassy_stm32::Peripherals) -> Hardware<'s> {
-        hardware_specific_init(peripherals)
+/// UART clocked with `kernel_clock` can run at `baudrate` by 16 times oversampling,
+/// e.g. 921600 is 2.1% off from 16MHz and refused.
+pub const fn is_baudrate_reachable(kernel_clock: u32, baudrate: u32) -> bool {
+    if baudrate == 0 {
+        return false;
+    }
+    let divisor = (kernel_clock as u64 + baudrate as u64 / 2) / baudrate as u64;
+    if divisor < 16 || divisor > 0xFFFF {
+        return false;
     }
+    let actual = kernel_clock as u64 / divisor;
+
+    actual.abs_diff(baudrate as u64) * 1000 <= baudrate as u64 * BAUDRATE_TOLERANCE_PERMILLE
+}
+
+/// CRC32 of packets with `LAPLUS_CRC` parameters, CRC peripheral on MCU
+pub trait Checksum {
+    /// Start over from `CRC_POLY_INIT`
+    fn reset(&mut self);
+
+    /// Feed bytes following what's fed since last reset, returns CRC so far
+    fn feed_bytes(&mut self, bytes: &[u8]) -> u32;
+}
+
+/// Flash access of OTA, offsets are relative to `FLASH_BASE`
+/// and checked by `region` before they get here.
+pub trait OtaFlash {
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), OtaError>;
+
+    /// Program erased flash, `WRITE_SIZE` aligned
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), OtaError>;
+
+    /// Erase pages from `from` until `to`, `PAGE_SIZE` aligned
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), OtaError>;
+}
+
+/// What OTA service takes from the board, MCU peripherals on target
+/// and `fake` ones on host tests
+pub trait Device {
+    type Crc: Checksum;
+    type Flash: OtaFlash;
+
+    /// Bytes host can send ahead while bootloader is busy with flash
+    const UART_RX_BUF_SIZE: usize;
+
+    fn crc(&mut self) -> &mut Self::Crc;
+
+    fn flash(&mut self) -> &mut Self::Flash;
+
+    fn serial_number(&self) -> [u8; 12];
+
+    fn crypto_nonce(&self) -> [u8; 12];
+
+    /// Option bytes as loaded at last reset
+    fn option_bytes(&self) -> OptionBytes;
+
+    /// Program `PROTECT_FLAG_*`, applied on [`Self::reload_option_bytes`]
+    fn program_option_bytes(&mut self, flags: u8) -> Result<(), OtaError>;
+
+    /// Drive LEDs with the pattern of `indicator` at this moment, call it periodically
+    fn show_indicator(&mut self, indicator: Indicator);
+
+    fn reset(&mut self) -> !;
+
+    fn jump_to_app(&mut self) -> !;
+
+    /// Load programmed option bytes, MCU resets right after
+    fn reload_option_bytes(&mut self) -> !;
 }
 
 pub struct SharedResource {
     pub cipher: ChaCha20,
     pub section_mark: SectionMark,
+    pub write_window: WriteWindow,
+    pub boot_diagnostics: BootDiagnostics,
+    /// Address answered on multi-drop bus
+    pub bus_address: u8,
+    /// What status LEDs are showing
+    pub indicator: Indicator,
+    /// Image length of sparse transfer, its pages are erased on `StartUpdate`
+    pub image_length: Option<u32>,
+    /// Transfer started by legacy `StartUpdate` (or none yet),
+    /// `WriteChunk` and `UpdateStatus` keep the layout old host tools know
+    pub legacy_transfer: bool,
 }
 
+pub(crate) const CIPHER_KEY: [u8; 32] = [0x42; 32]; // fill any key.
+
 impl SharedResource {
     /// Initialize necessary shared resource
-    fn init() -> Self {
-        let key = [0x42; 32]; // fill any key.
-        let nonce = crypto_nonce();
+    fn init(hardware: &impl Device, boot_diagnostics: BootDiagnostics) -> Self {
+        let nonce = hardware.crypto_nonce();
 
         Self {
-            cipher: ChaCha20::new(&key.into(), &nonce.into()),
+            cipher: ChaCha20::new(&CIPHER_KEY.into(), &nonce.into()),
             section_mark: SectionMark::new(),
+            write_window: WriteWindow::new(),
+            boot_diagnostics,
+            bus_address: derive_bus_address(&hardware.serial_number()),
+            indicator: Indicator::WaitingHost,
+            image_length: None,
+            legacy_transfer: true,
         }
     }
+
+    /// Restart cipher stream with `nonce`, e.g. one given by host for broadcast
+    pub fn reset_cipher(&mut self, nonce: &[u8; 12]) {
+        self.cipher = ChaCha20::new(&CIPHER_KEY.into(), nonce.into());
+    }
 }
 
-#[allow(dead_code)]
-pub struct Board<'s> {
-    pub hardware: Hardware<'s>,
+pub struct Board<D: Device> {
+    pub hardware: D,
     pub shared_resource: SharedResource,
 }
 
-impl Board<'static> {
-    pub fn init() -> Self {
-        let peripherals = Hardware::mcu_pre_init();
-
-        let hardware: Hardware = Hardware::hardware_init(peripherals);
-        let shared_resource = SharedResource::init();
+impl<D: Device> Board<D> {
+    /// Board resident in bootloader for `boot_diagnostics.reason`
+    pub fn new(hardware: D, boot_diagnostics: BootDiagnostics) -> Self {
+        let shared_resource = SharedResource::init(&hardware, boot_diagnostics);
 
         Self {
             hardware,
             shared_resource,
         }
     }
-
-    pub fn get_nonce() -> [u8; 12] {
-        crypto_nonce()
-    }
-
-    pub fn get_serial_number() -> [u8; 12] {
-        serial_number()
-    }
 }
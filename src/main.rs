@@ -10,9 +10,12 @@
 #![feature(type_alias_impl_trait)]
 #![feature(impl_trait_in_assoc_type)]
 #![feature(inline_const_pat)]
-#![no_main]
-#![no_std]
+#![cfg_attr(target_arch = "arm", no_main)]
+#![cfg_attr(target_arch = "arm", no_std)]
+// host build only runs unit tests, firmware entry is left out
+#![cfg_attr(not(target_arch = "arm"), allow(dead_code, unused_imports))]
 
+#[cfg(target_arch = "arm")]
 pub mod pac {
     pub use embassy_stm32::pac::Interrupt as interrupt;
     pub use embassy_stm32::pac::*;
@@ -22,134 +25,169 @@ mod boards;
 
 pub(crate) mod types;
 
+mod service;
+
+#[cfg(target_arch = "arm")]
 use cortex_m_rt::entry;
 use embassy_time::{Duration, Instant};
-use embedded_io::*;
 // use hex_literal::hex;
+#[cfg(target_arch = "arm")]
 use panic_abort as _;
 
 use crate::boards::Board;
-use crate::types::ota::*;
-
-type StackedBufferRxIndex = usize;
-
-const WAIT_DURATION_RX: Duration = Duration::from_millis(200); // heuristic value
-
-pub enum Key {
-    /// Only transmit thorugh UART, usize is length to send
-    Tx(usize),
-    /// Reset after transmit thorugh UART, usize is length to send
-    TxAndReset(usize),
-    /// Jump to app region after transmit thorugh UART, usize is length to send
-    TxAndJump(usize),
-}
-
+#[cfg(target_arch = "arm")]
+use crate::boards::Hardware;
+use crate::service::{OtaService, Transport};
+use crate::types::app_image::AppInfo;
+use crate::types::boot_reason::{BootDiagnostics, BootReason, ResetFlags};
+use crate::types::indicator::Indicator;
+use crate::types::mailbox::{Handoff, Mailbox, MailboxCommand};
+
+#[cfg(target_arch = "arm")]
 #[entry]
 fn main() -> ! {
     let raw_boot_parm = unsafe { types::read_bootloader_param() };
-    let mut board = boards::Board::init();
-    let mut rx_buf: [u8; 1024] = [0; 1024];
-    let mut tx_buf: [u8; REASONABLE_TX_BUF] = [0; REASONABLE_TX_BUF];
-
-    let mut stacked: StackedBufferRxIndex = 0;
-    let mut last_rx = Instant::now();
+    let mailbox = Mailbox::take();
+    let mailbox_command = mailbox.command();
+    let reset_flags = ResetFlags::read();
+    let (mut hardware, mut transport) = Hardware::init();
+
+    if mailbox_command == Some(MailboxCommand::Confirm) {
+        // on failure application just stays unconfirmed, `AppInfo` tells the host
+        let _ = AppInfo::inspect(&mut hardware).confirm(&mut hardware.flash);
+    }
 
     // if there's any condition to settle on bootloader
-    // otherwise jump to application
-    if raw_boot_parm != types::BOOTLOADER_KEY {
-        // Check Gpio
-        for _ in 0..50 {
-            if board.hardware.force_bootloader.is_high() {
-                unsafe { types::jump_to_app() }
+    // otherwise jump to application.
+    // Normal boot pays `ENTRY_MAGIC_WINDOW` and image CRC, pins cost only when active.
+    let boot_reason = if mailbox_command == Some(MailboxCommand::EnterOta) {
+        BootReason::Mailbox
+    } else if raw_boot_parm == types::BOOTLOADER_KEY {
+        BootReason::BootParam
+    } else if force_bootloader_held(&mut hardware) {
+        BootReason::ForcePin
+    } else if host_rx_break_held(&mut hardware) {
+        BootReason::RxBreak
+    } else if entry_magic_received(&mut transport) {
+        drain_entry_magic(&mut transport);
+
+        BootReason::EntryMagic
+    } else {
+        let app_info = AppInfo::inspect(&mut hardware);
+
+        if !app_info.is_bootable() {
+            BootReason::InvalidApp
+        } else {
+            Mailbox::new_handoff(Handoff {
+                reset_flags: reset_flags.0,
+                trial: app_info.is_trial(),
+            })
+            .post();
+
+            unsafe { types::jump_to_app() }
+        }
+    };
+
+    let mut board = Board::new(
+        hardware,
+        BootDiagnostics {
+            reason: boot_reason,
+            reset_flags,
+            boot_param: raw_boot_parm,
+            mailbox_command: mailbox_command.map_or(0, |x| x as u8),
+        },
+    );
+
+    match boot_reason {
+        // application may know its address on bus better, e.g. from DIP switch
+        BootReason::Mailbox => {
+            if let bus_address @ 0x01..=0xFE = mailbox.argument as u8 {
+                board.shared_resource.bus_address = bus_address;
             }
-            board.hardware.delay.delay_ms(1);
         }
+        BootReason::InvalidApp => {
+            board.shared_resource.indicator = Indicator::InvalidApp;
+        }
+        _ => {}
     }
 
+    let mut service = OtaService::new();
+
     loop {
-        let rx_len = match board.hardware.rx.read(&mut rx_buf) {
-            Ok(0) => {
-                let now = Instant::now();
-                // when hang too much, just reset uart stacking
-                if (now - last_rx) > WAIT_DURATION_RX {
-                    last_rx = now;
-                    stacked = 0;
-                }
-                continue;
-            }
-            Ok(n) => n,
-            Err(_) => {
-                board.hardware.delay.delay_ms(1);
-                last_rx = Instant::now();
-                stacked = 0;
+        service.poll(&mut board, &mut transport);
+    }
+}
 
-                continue;
-            }
+/// Host build only runs unit tests
+#[cfg(not(target_arch = "arm"))]
+fn main() {}
+
+/// `force_bootloader` pin is kept active for 50ms
+#[cfg(target_arch = "arm")]
+fn force_bootloader_held(hardware: &mut Hardware) -> bool {
+    held_for_50ms(hardware, |hardware| hardware.is_force_bootloader_active())
+}
+
+/// Host keeps UART RX line in break for 50ms, for enclosures hiding the entry pin
+#[cfg(target_arch = "arm")]
+fn host_rx_break_held(hardware: &mut Hardware) -> bool {
+    held_for_50ms(hardware, |hardware| hardware.is_host_rx_break())
+}
+
+#[cfg(target_arch = "arm")]
+fn held_for_50ms(hardware: &mut Hardware, active: fn(&Hardware) -> bool) -> bool {
+    for _ in 0..50 {
+        if !active(hardware) {
+            return false;
+        }
+        hardware.delay.delay_ms(1);
+    }
+
+    true
+}
+
+/// Host repeats it while resetting the board, none of its bytes can start a frame
+const ENTRY_MAGIC: [u8; 4] = *b"OTA!";
+/// How long to listen for `ENTRY_MAGIC` before jumping to application
+const ENTRY_MAGIC_WINDOW: Duration = Duration::from_millis(20);
+/// Give up waiting host to stop repeating `ENTRY_MAGIC`
+const ENTRY_MAGIC_DRAIN_LIMIT: Duration = Duration::from_millis(1000);
+
+/// `ENTRY_MAGIC` arrives within `ENTRY_MAGIC_WINDOW` after reset
+fn entry_magic_received<T: Transport>(transport: &mut T) -> bool {
+    let deadline = Instant::now() + ENTRY_MAGIC_WINDOW;
+    let mut matched = 0;
+    let mut byte = [0u8; 1];
+
+    while Instant::now() < deadline {
+        if !matches!(transport.read(&mut byte), Ok(1)) {
+            continue;
+        }
+
+        matched = match byte[0] {
+            x if x == ENTRY_MAGIC[matched] => matched + 1,
+            x if x == ENTRY_MAGIC[0] => 1,
+            _ => 0,
         };
 
-        match crate::types::ota::test_packet(&rx_buf[..stacked + rx_len]) {
-            Ok(cmd) => {
-                let key = match cmd {
-                    RequestForm::Handshake => Key::Tx(on_tx_buffer!(
-                        tx_buf,
-                        HandshakeForm,
-                        HandshakeForm::response_new()
-                    )),
-                    RequestForm::DeviceInfo => Key::Tx(on_tx_buffer!(
-                        tx_buf,
-                        DeviceInfoResponseForm,
-                        DeviceInfoResponseForm::new(&mut board)
-                    )),
-                    RequestForm::StartUpdate => Key::Tx(on_tx_buffer!(
-                        tx_buf,
-                        StartUpdateResponseForm,
-                        StartUpdateResponseForm::new(&mut board)
-                    )),
-                    RequestForm::WriteChunk(chunk) => Key::Tx(on_tx_buffer!(
-                        tx_buf,
-                        WriteChunkResponseForm,
-                        WriteChunkResponseForm::new(chunk.try_flash(&mut board))
-                    )),
-                    RequestForm::UpdateStatus => Key::Tx(on_tx_buffer!(
-                        tx_buf,
-                        UpdateStatusResponseForm,
-                        UpdateStatusResponseForm::new(&mut board)
-                    )),
-                    RequestForm::Reset => {
-                        Key::TxAndReset(on_tx_buffer!(tx_buf, ResetForm, ResetForm::response_new()))
-                    }
-                    RequestForm::JumpToApplication => Key::TxAndJump(on_tx_buffer!(
-                        tx_buf,
-                        JumpToApplicationForm,
-                        JumpToApplicationForm::response_new()
-                    )),
-                };
-
-                match key {
-                    Key::Tx(x) => {
-                        let _ = board.hardware.tx.write(&tx_buf[..x]);
-                    }
-                    Key::TxAndReset(x) => {
-                        let _ = board.hardware.tx.write(&tx_buf[..x]);
-
-                        cortex_m::peripheral::SCB::sys_reset();
-                    }
-                    Key::TxAndJump(x) => {
-                        let _ = board.hardware.tx.write(&tx_buf[..x]);
-
-                        unsafe { types::jump_to_app() }
-                    }
-                }
-            }
-            Err(e) => {
-                if e == OtaError::OutOfRange {
-                    stacked += rx_len;
-                } else {
-                    stacked = 0;
-                }
-            }
+        if matched == ENTRY_MAGIC.len() {
+            return true;
         }
+    }
 
-        last_rx = Instant::now();
+    false
+}
+
+/// Discard repeated `ENTRY_MAGIC` until host is quiet for `ENTRY_MAGIC_WINDOW`,
+/// so OTA service starts with empty RX
+fn drain_entry_magic<T: Transport>(transport: &mut T) {
+    let deadline = Instant::now() + ENTRY_MAGIC_DRAIN_LIMIT;
+    let mut last_rx = Instant::now();
+    let mut buf = [0u8; 16];
+
+    while Instant::now() < deadline && (Instant::now() - last_rx) < ENTRY_MAGIC_WINDOW {
+        if matches!(transport.read(&mut buf), Ok(n) if n != 0) {
+            last_rx = Instant::now();
+        }
     }
 }
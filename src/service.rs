@@ -0,0 +1,822 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! OTA request/response loop over any byte stream [`Transport`].
+//! Frames are reassembled, opened and answered here, `main` only decides
+//! whether to stay in bootloader and keeps polling.
+
+use embassy_time::{Duration, Instant};
+use embedded_io::{Read, Write};
+
+use crate::boards::{Board, Device, DEFAULT_BAUDRATE};
+use crate::on_tx_buffer;
+use crate::types::frame::{split_address, Envelope, BROADCAST_ADDRESS, RESPONSE_FORM_OFFSET};
+use crate::types::frame_assembler::{FrameAssembler, Rejected};
+use crate::types::ota::*;
+
+const WAIT_DURATION_RX: Duration = Duration::from_millis(200); // heuristic value
+/// Revert to default baudrate when host doesn't talk with new baudrate in time
+const WAIT_DURATION_BAUDRATE: Duration = Duration::from_millis(1000);
+/// On RS-485 bus only frames addressed to this device are answered,
+/// anything else could be other devices talking.
+const BUS_MODE: bool = cfg!(feature = "rs485");
+
+/// Byte stream the OTA protocol runs over, `read` returns `Ok(0)` instead of blocking
+pub trait Transport: Read + Write {
+    /// Switch line speed, pending TX data goes out with the old one.
+    /// Transport without line speed refuses it.
+    fn set_baudrate(&mut self, _baudrate: u32) -> Result<(), OtaError> {
+        Err(OtaError::InvalidArgument)
+    }
+
+    /// Whether [`Self::set_baudrate`] would take `baudrate`, asked before answering host
+    fn check_baudrate(&self, _baudrate: u32) -> Result<(), OtaError> {
+        Err(OtaError::InvalidArgument)
+    }
+}
+
+pub enum Key {
+    /// Nothing to transmit, e.g. chunk in the middle of window
+    Nothing,
+    /// Only transmit thorugh transport, usize is length to send
+    Tx(usize),
+    /// Reset after transmit thorugh transport, usize is length to send
+    TxAndReset(usize),
+    /// Jump to app region after transmit thorugh transport, usize is length to send
+    TxAndJump(usize),
+    /// Change baudrate after transmit thorugh transport, usize is length to send
+    TxAndSetBaudRate(usize, u32),
+    /// Reload option bytes (MCU resets) after transmit thorugh transport, usize is length to send
+    TxAndReloadOptionBytes(usize),
+}
+
+pub struct OtaService {
+    frame_buf: [u8; REASONABLE_RX_BUF],
+    tx_buf: [u8; REASONABLE_TX_BUF],
+    assembler: FrameAssembler<REASONABLE_RX_BUF>,
+    last_rx: Instant,
+    /// Some when baudrate is changed but no valid frame received yet with it
+    baudrate_deadline: Option<Instant>,
+}
+
+impl OtaService {
+    pub fn new() -> Self {
+        Self {
+            frame_buf: [0; REASONABLE_RX_BUF],
+            tx_buf: [0; REASONABLE_TX_BUF],
+            assembler: FrameAssembler::new(),
+            last_rx: Instant::now(),
+            baudrate_deadline: None,
+        }
+    }
+
+    /// Receive what transport has, then serve every complete request in it
+    pub fn poll<D: Device, T: Transport>(&mut self, board: &mut Board<D>, transport: &mut T) {
+        let mut rx_buf: [u8; 64] = [0; 64];
+
+        board
+            .hardware
+            .show_indicator(board.shared_resource.indicator);
+
+        if let Some(deadline) = self.baudrate_deadline {
+            if Instant::now() > deadline {
+                let _ = transport.set_baudrate(DEFAULT_BAUDRATE);
+                self.baudrate_deadline = None;
+                self.assembler.clear();
+            }
+        }
+
+        let rx_len = match transport.read(&mut rx_buf) {
+            Ok(0) => {
+                let now = Instant::now();
+                // when hang too much, drop partial frame
+                if (now - self.last_rx) > WAIT_DURATION_RX {
+                    self.last_rx = now;
+                    self.assembler.clear();
+                }
+                return;
+            }
+            Ok(n) => n,
+            Err(_) => {
+                embassy_time::block_for(Duration::from_millis(1));
+                self.last_rx = Instant::now();
+                self.assembler.clear();
+
+                return;
+            }
+        };
+
+        self.last_rx = Instant::now();
+        self.assembler.push(&rx_buf[..rx_len]);
+
+        self.serve(board, transport);
+    }
+
+    /// Answer every complete frame in the assembler
+    fn serve<D: Device, T: Transport>(&mut self, board: &mut Board<D>, transport: &mut T) {
+        let tx_buf = &mut self.tx_buf;
+
+        loop {
+            let format = RequestFrame {
+                chunk_size: board.shared_resource.section_mark.chunk_size(),
+                legacy_transfer: board.shared_resource.legacy_transfer,
+            };
+
+            let frame = match self.assembler.pop(&format, &mut self.frame_buf) {
+                Ok(frame) => frame,
+                Err(OtaError::OutOfRange) => break,
+                Err(e) => {
+                    // noise and entry magic are dropped silently, host isn't talking to us yet
+                    let rejected = rejected_header(&self.assembler, &mut self.frame_buf);
+                    if let (Some((envelope, address, command)), false) = (rejected, BUS_MODE) {
+                        nack(board, transport, tx_buf, envelope, address, e, command);
+                    }
+                    continue;
+                }
+            };
+
+            let (address, frame) = split_address(frame);
+            let broadcast = address == Some(BROADCAST_ADDRESS);
+            let accepted = match address {
+                Some(address) => broadcast || address == board.shared_resource.bus_address,
+                None => !BUS_MODE,
+            };
+            if !accepted {
+                continue;
+            }
+
+            let (rejected_envelope, rejected_command) = Envelope::peek(frame);
+            let (envelope, packet) = match Envelope::open(frame, board.hardware.crc()) {
+                Ok(x) => x,
+                Err(e) => {
+                    nack(
+                        board,
+                        transport,
+                        tx_buf,
+                        rejected_envelope,
+                        address,
+                        e,
+                        rejected_command,
+                    );
+                    continue;
+                }
+            };
+
+            let cmd = match test_packet(packet, &format) {
+                Ok(cmd) => cmd,
+                Err(e) => {
+                    nack(
+                        board,
+                        transport,
+                        tx_buf,
+                        envelope,
+                        address,
+                        e,
+                        rejected_command,
+                    );
+                    continue;
+                }
+            };
+
+            self.baudrate_deadline = None;
+
+            let form_buf = &mut tx_buf[RESPONSE_FORM_OFFSET..];
+            match dispatch(board, transport, form_buf, cmd, envelope, broadcast) {
+                Key::Nothing => {}
+                Key::Tx(x) => {
+                    send(board, transport, tx_buf, envelope, address, x);
+                }
+                Key::TxAndReset(x) => {
+                    send(board, transport, tx_buf, envelope, address, x);
+
+                    board.hardware.reset();
+                }
+                Key::TxAndJump(x) => {
+                    send(board, transport, tx_buf, envelope, address, x);
+
+                    board.hardware.jump_to_app();
+                }
+                Key::TxAndReloadOptionBytes(x) => {
+                    send(board, transport, tx_buf, envelope, address, x);
+
+                    board.hardware.reload_option_bytes();
+                }
+                Key::TxAndSetBaudRate(x, baudrate) => {
+                    send(board, transport, tx_buf, envelope, address, x);
+
+                    if transport.set_baudrate(baudrate).is_ok() {
+                        self.baudrate_deadline = Some(Instant::now() + WAIT_DURATION_BAUDRATE);
+                        // rest of bytes are received with previous baudrate
+                        self.assembler.clear();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Envelope, address and command byte of the frame just rejected by `assembler`,
+/// `None` when there was no frame in the discarded bytes
+fn rejected_header<const N: usize>(
+    assembler: &FrameAssembler<N>,
+    frame_buf: &mut [u8],
+) -> Option<(Envelope, Option<u8>, Option<u8>)> {
+    match assembler.rejected() {
+        Rejected::Nothing => None,
+        // only v1 frames are recognized by their end, address is lost with start byte
+        Rejected::Headless => Some((Envelope::V1, None, None)),
+        Rejected::Frame(command) => {
+            // `pop` leaves the head of rejected frame in `frame_buf`
+            let (address, frame) = split_address(frame_buf);
+            let (envelope, _) = Envelope::peek(frame);
+
+            Some((envelope, address, command))
+        }
+    }
+}
+
+/// Handle a request came in `envelope`, response form is written on `form_buf`
+pub fn dispatch<D: Device, T: Transport>(
+    board: &mut Board<D>,
+    transport: &T,
+    form_buf: &mut [u8],
+    cmd: RequestForm,
+    envelope: Envelope,
+    broadcast: bool,
+) -> Key {
+    match cmd {
+        RequestForm::Handshake(None) => Key::Tx(on_tx_buffer!(
+            form_buf,
+            HandshakeForm,
+            HandshakeForm::response_new()
+        )),
+        RequestForm::Handshake(Some(request)) => Key::Tx(on_tx_buffer!(
+            form_buf,
+            HandshakeResponseForm,
+            HandshakeResponseForm::new(board, request)
+        )),
+        RequestForm::DeviceInfo if envelope == Envelope::V1 => Key::Tx(on_tx_buffer!(
+            form_buf,
+            DeviceInfoResponseForm,
+            DeviceInfoResponseForm::new(board)
+        )),
+        RequestForm::DeviceInfo => Key::Tx(on_tx_buffer!(
+            form_buf,
+            DeviceInfoV2ResponseForm,
+            DeviceInfoV2ResponseForm::new(board)
+        )),
+        RequestForm::MemoryMap => Key::Tx(on_tx_buffer!(
+            form_buf,
+            MemoryMapResponseForm,
+            MemoryMapResponseForm::new(board)
+        )),
+        RequestForm::BootloaderInfo => Key::Tx(on_tx_buffer!(
+            form_buf,
+            BootloaderInfoResponseForm,
+            BootloaderInfoResponseForm::new(board)
+        )),
+        RequestForm::AppInfo => Key::Tx(on_tx_buffer!(
+            form_buf,
+            AppInfoResponseForm,
+            AppInfoResponseForm::new(board)
+        )),
+        RequestForm::Diagnostics => Key::Tx(on_tx_buffer!(
+            form_buf,
+            DiagnosticsResponseForm,
+            DiagnosticsResponseForm::new(board)
+        )),
+        RequestForm::OptionBytes => Key::Tx(on_tx_buffer!(
+            form_buf,
+            OptionBytesResponseForm,
+            OptionBytesResponseForm::new(board)
+        )),
+        RequestForm::SetBaudRate(request) => match request
+            .baudrate()
+            .and_then(|baudrate| transport.check_baudrate(baudrate).map(|_| baudrate))
+        {
+            Ok(baudrate) => Key::TxAndSetBaudRate(
+                on_tx_buffer!(
+                    form_buf,
+                    SetBaudRateResponseForm,
+                    SetBaudRateResponseForm::new(Ok(baudrate))
+                ),
+                baudrate,
+            ),
+            Err(e) => Key::Tx(on_tx_buffer!(
+                form_buf,
+                SetBaudRateResponseForm,
+                SetBaudRateResponseForm::new(Err(e))
+            )),
+        },
+        RequestForm::LegacyStartUpdate => Key::Tx(on_tx_buffer!(
+            form_buf,
+            LegacyStartUpdateResponseForm,
+            LegacyStartUpdateResponseForm::new(board)
+        )),
+        RequestForm::StartUpdate {
+            payload_exponent,
+            host_nonce,
+            image_length,
+        } => {
+            match StartUpdateResponseForm::new(board, payload_exponent, host_nonce, image_length) {
+                Ok(response) => Key::Tx(on_tx_buffer!(form_buf, StartUpdateResponseForm, response)),
+                Err(e) => Key::Tx(on_tx_buffer!(
+                    form_buf,
+                    NackResponseForm,
+                    NackResponseForm::new(e, Some(Command::StartUpdate as u8))
+                )),
+            }
+        }
+        RequestForm::WriteChunk(chunk) => match chunk.process(board, broadcast) {
+            Some(response) => Key::Tx(on_tx_buffer!(form_buf, WriteChunkResponseForm, response)),
+            None => Key::Nothing,
+        },
+        RequestForm::LegacyWriteChunk(chunk) => Key::Tx(on_tx_buffer!(
+            form_buf,
+            LegacyWriteChunkResponseForm,
+            chunk.process(board)
+        )),
+        // hard to undo, so it's applied to one device at a time and never by broadcast
+        RequestForm::SetProtection(_) if broadcast => Key::Nothing,
+        RequestForm::SetProtection(request) => match request.apply(board) {
+            Ok(_) => Key::TxAndReloadOptionBytes(on_tx_buffer!(
+                form_buf,
+                SetProtectionResponseForm,
+                SetProtectionResponseForm::new(Ok(()), request.flags)
+            )),
+            Err(e) => Key::Tx(on_tx_buffer!(
+                form_buf,
+                SetProtectionResponseForm,
+                SetProtectionResponseForm::new(Err(e), request.flags)
+            )),
+        },
+        RequestForm::UpdateStatus if board.shared_resource.legacy_transfer => {
+            Key::Tx(on_tx_buffer!(
+                form_buf,
+                LegacyUpdateStatusResponseForm,
+                LegacyUpdateStatusResponseForm::new(board)
+            ))
+        }
+        RequestForm::UpdateStatus => Key::Tx(on_tx_buffer!(
+            form_buf,
+            UpdateStatusResponseForm,
+            UpdateStatusResponseForm::new(board)
+        )),
+        RequestForm::Finalize(request) => Key::Tx(on_tx_buffer!(
+            form_buf,
+            FinalizeResponseForm,
+            FinalizeResponseForm::new(board, u32::from_le_bytes(request.image_length))
+        )),
+        RequestForm::Reset => Key::TxAndReset(on_tx_buffer!(
+            form_buf,
+            ResetForm,
+            ResetForm::response_new()
+        )),
+        RequestForm::JumpToApplication => Key::TxAndJump(on_tx_buffer!(
+            form_buf,
+            JumpToApplicationForm,
+            JumpToApplicationForm::response_new()
+        )),
+    }
+}
+
+/// Transmit response form written at `tx_buf[RESPONSE_FORM_OFFSET..]`,
+/// `address` is of the request to answer in the same way. Broadcast is never answered.
+fn send<D: Device, T: Transport>(
+    board: &mut Board<D>,
+    transport: &mut T,
+    tx_buf: &mut [u8],
+    envelope: Envelope,
+    address: Option<u8>,
+    form_len: usize,
+) {
+    if address == Some(BROADCAST_ADDRESS) {
+        return;
+    }
+
+    let frame = envelope.seal(tx_buf, form_len, address, board.hardware.crc());
+
+    let _ = transport.write_all(frame);
+    let _ = transport.flush();
+}
+
+/// Answer rejected frame, so host can retry without waiting timeout
+fn nack<D: Device, T: Transport>(
+    board: &mut Board<D>,
+    transport: &mut T,
+    tx_buf: &mut [u8],
+    envelope: Envelope,
+    address: Option<u8>,
+    error: OtaError,
+    command: Option<u8>,
+) {
+    let form_buf = &mut tx_buf[RESPONSE_FORM_OFFSET..];
+    let x = on_tx_buffer!(
+        form_buf,
+        NackResponseForm,
+        NackResponseForm::new(error, command)
+    );
+
+    send(board, transport, tx_buf, envelope, address, x);
+}
+
+#[cfg(test)]
+mod tests {
+    use chacha20::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+    use chacha20::ChaCha20;
+
+    use super::*;
+    use crate::boards::fake::{fake_board, FakeHardware, FakeTransport, SERIAL_NUMBER};
+    use crate::boards::CIPHER_KEY;
+    use crate::types::capability;
+    use crate::types::frame::{
+        PROTOCOL_VERSION_V1, PROTOCOL_VERSION_V2, SOF_V2_REQUEST, SOF_V2_RESPONSE,
+    };
+    use crate::types::option_bytes::PROTECT_FLAG_RDP_LEVEL1;
+    use crate::types::section_mark::{DEFAULT_CHUNK_BIT_IDX, REMAIN_OFFSET, REMAIN_SIZE};
+    use crate::types::std_crc::std_crc;
+
+    /// Feed `rx` as host would and return what bootloader answered
+    fn round_trip(rx: &[u8]) -> Vec<u8> {
+        round_trip_on(&mut fake_board(), rx)
+    }
+
+    fn round_trip_on(board: &mut Board<FakeHardware>, rx: &[u8]) -> Vec<u8> {
+        let mut transport = FakeTransport::new(rx);
+        let mut service = OtaService::new();
+
+        while !transport.rx.is_empty() {
+            service.poll(board, &mut transport);
+        }
+
+        transport.tx
+    }
+
+    #[test]
+    fn handshake_v1() {
+        let host_versions = (1 << PROTOCOL_VERSION_V1) | (1 << PROTOCOL_VERSION_V2);
+        let tx = round_trip(&[
+            Sof::Request as u8,
+            Command::Handshake as u8,
+            host_versions,
+            0xFF,
+        ]);
+
+        assert_eq!(tx.len(), core::mem::size_of::<HandshakeResponseForm>());
+        assert_eq!(tx[0], Sof::Response as u8);
+        assert_eq!(tx[1], Command::Handshake as u8);
+        assert_eq!(tx[4], capability::SUPPORTED_VERSIONS);
+        assert_eq!(tx[5], PROTOCOL_VERSION_V2);
+        assert_eq!(tx[6..10], capability::CAPABILITIES.to_le_bytes());
+        assert_eq!(tx[10], EOF_SIGNATURE);
+        assert_eq!(tx[2..4], (std_crc(&tx[4..10]) as u16).to_le_bytes());
+    }
+
+    #[test]
+    fn device_info_v2() {
+        let mut board = fake_board();
+        // readout protection level 1
+        board.hardware.option_bytes.optr = 0xFFFF_FEBB;
+        let sequence: u16 = 0x1234;
+        let mut rx = vec![SOF_V2_REQUEST, PROTOCOL_VERSION_V2, 0, 0];
+        rx.extend_from_slice(&sequence.to_le_bytes());
+        rx.push(Command::DeviceInfo as u8);
+        rx.extend_from_slice(&std_crc(&rx).to_le_bytes());
+
+        let tx = round_trip_on(&mut board, &rx);
+
+        // form without SOF, command and EOF, between v2 header and CRC
+        let length = core::mem::size_of::<DeviceInfoV2ResponseForm>() - 3;
+        let crc_pos = 7 + length;
+        assert_eq!(tx.len(), crc_pos + 4);
+        assert_eq!(tx[..2], [SOF_V2_RESPONSE, PROTOCOL_VERSION_V2]);
+        assert_eq!(tx[2..4], (length as u16).to_le_bytes());
+        assert_eq!(tx[4..6], sequence.to_le_bytes());
+        assert_eq!(tx[6], Command::DeviceInfo as u8);
+        assert_eq!(tx[crc_pos..], std_crc(&tx[..crc_pos]).to_le_bytes());
+
+        let form = &tx[7..crc_pos];
+        assert_eq!(form[2], PROTOCOL_VERSION_V2);
+        assert_eq!(form[3], DEFAULT_CHUNK_BIT_IDX as u8);
+        assert_eq!(form[4..16], SERIAL_NUMBER);
+        assert_eq!(form[16], PROTECT_FLAG_RDP_LEVEL1);
+        assert_eq!(form[..2], (std_crc(&form[2..17]) as u16).to_le_bytes());
+    }
+
+    #[test]
+    fn noise_and_entry_magic_are_not_answered() {
+        let tx = round_trip(b"\x00\x13OTA!OTA!OTA!");
+
+        assert!(tx.is_empty());
+    }
+
+    #[test]
+    fn missing_eof_is_answered_in_v1() {
+        let tx = round_trip(&[Sof::Request as u8, Command::DeviceInfo as u8, 0x00]);
+
+        assert_eq!(
+            tx,
+            [
+                Sof::Response as u8,
+                Command::Nack as u8,
+                OtaError::MissingEof as u8,
+                Command::DeviceInfo as u8,
+                EOF_SIGNATURE,
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_command_is_answered_with_its_byte() {
+        let tx = round_trip(&[Sof::Request as u8, 0x99, EOF_SIGNATURE]);
+
+        // rest of the rejected frame is not answered again as headless one
+        assert_eq!(
+            tx,
+            [
+                Sof::Response as u8,
+                Command::Nack as u8,
+                OtaError::UnknownCommand as u8,
+                0x99,
+                EOF_SIGNATURE,
+            ]
+        );
+    }
+
+    #[test]
+    fn frame_without_sof_is_answered() {
+        let tx = round_trip(&[Command::DeviceInfo as u8, EOF_SIGNATURE]);
+
+        assert_eq!(
+            tx,
+            [
+                Sof::Response as u8,
+                Command::Nack as u8,
+                OtaError::MissingSof as u8,
+                0,
+                EOF_SIGNATURE,
+            ]
+        );
+    }
+
+    #[test]
+    fn rejected_v2_frame_is_answered_in_v2() {
+        let sequence: u16 = 0x0102;
+        // unknown protocol version in v2 header
+        let mut rx = vec![SOF_V2_REQUEST, 0x03, 0, 0];
+        rx.extend_from_slice(&sequence.to_le_bytes());
+        rx.push(Command::DeviceInfo as u8);
+
+        let tx = round_trip(&rx);
+
+        assert_eq!(tx.len(), 7 + 2 + 4);
+        assert_eq!(tx[..4], [SOF_V2_RESPONSE, PROTOCOL_VERSION_V2, 2, 0]);
+        assert_eq!(tx[4..6], sequence.to_le_bytes());
+        assert_eq!(
+            tx[6..9],
+            [
+                Command::Nack as u8,
+                OtaError::UnsupportedVersion as u8,
+                Command::DeviceInfo as u8
+            ]
+        );
+        assert_eq!(tx[9..], std_crc(&tx[..9]).to_le_bytes());
+    }
+
+    #[test]
+    fn write_chunk_is_decrypted_into_flash() {
+        let mut board = fake_board();
+        let chunk_size = board.shared_resource.section_mark.chunk_size();
+        // second chunk, keystream starts from its offset in application region
+        let offset = REMAIN_OFFSET + chunk_size;
+        let plain: Vec<u8> = (0..chunk_size).map(|i| i as u8).collect();
+
+        let mut encrypted = plain.clone();
+        let mut cipher = ChaCha20::new(&CIPHER_KEY.into(), &SERIAL_NUMBER.into());
+        cipher.seek(chunk_size as u32);
+        cipher.apply_keystream(&mut encrypted);
+
+        // negotiated transfer, chunks carry sequence and flags
+        let mut rx = vec![
+            Sof::Request as u8,
+            Command::StartUpdate as u8,
+            DEFAULT_CHUNK_BIT_IDX as u8,
+            EOF_SIGNATURE,
+        ];
+        let mut chunk = vec![0u8; REASONABLE_RX_BUF];
+        let len = WriteChunkRequestForm::new_std(
+            0,
+            WRITE_CHUNK_FLAG_ACK | WRITE_CHUNK_FLAG_UNSEQUENCED,
+            offset as u32,
+            &encrypted,
+            &mut chunk,
+        )
+        .unwrap_or_else(|_| panic!());
+        rx.extend_from_slice(&chunk[..len]);
+
+        let tx = round_trip_on(&mut board, &rx);
+        let tx = &tx[core::mem::size_of::<StartUpdateResponseForm>()..];
+
+        assert_eq!(tx[..3], [Sof::Response as u8, Command::WriteChunk as u8, 0]);
+        assert_eq!(
+            board.hardware.flash.memory[offset..offset + chunk_size],
+            plain
+        );
+        assert!(board.shared_resource.section_mark.is_marked(offset as u32));
+    }
+
+    /// Chunks are acknowledged together on the last of window, a gap is NACKed only once
+    #[test]
+    fn write_chunks_are_acknowledged_cumulatively() {
+        let mut board = fake_board();
+        let chunk_size = board.shared_resource.section_mark.chunk_size();
+        let encrypted = vec![0u8; chunk_size];
+
+        let mut rx = vec![
+            Sof::Request as u8,
+            Command::StartUpdate as u8,
+            DEFAULT_CHUNK_BIT_IDX as u8,
+            EOF_SIGNATURE,
+        ];
+        // 3 and 4 arrive before lost 2, then 2 is resent asking acknowledge
+        for (sequence, flags) in [(0, 0), (1, 0), (3, 0), (4, 0), (2, WRITE_CHUNK_FLAG_ACK)] {
+            let offset = REMAIN_OFFSET + sequence as usize * chunk_size;
+            let mut chunk = vec![0u8; REASONABLE_RX_BUF];
+            let len = WriteChunkRequestForm::new_std(
+                sequence,
+                flags,
+                offset as u32,
+                &encrypted,
+                &mut chunk,
+            )
+            .unwrap_or_else(|_| panic!());
+            rx.extend_from_slice(&chunk[..len]);
+        }
+
+        let tx = round_trip_on(&mut board, &rx);
+        let tx = &tx[core::mem::size_of::<StartUpdateResponseForm>()..];
+
+        let response = |result: OtaError, sequence: u16, next_sequence: u16| {
+            let mut form = vec![Sof::Response as u8, Command::WriteChunk as u8, result as u8];
+            form.extend_from_slice(&sequence.to_le_bytes());
+            form.extend_from_slice(&next_sequence.to_le_bytes());
+            form.push(EOF_SIGNATURE);
+            form
+        };
+        let mut expected = response(OtaError::SequenceGap, 3, 2);
+        expected.extend(response(OtaError::Nothing, 2, 3));
+        assert_eq!(tx, expected);
+        assert_eq!(board.shared_resource.write_window.next_sequence, 3);
+    }
+
+    /// Sparse `StartUpdate` erases the image pages, `Finalize` marks chunks left erased
+    #[test]
+    fn sparse_transfer_marks_erased_chunks() {
+        let mut board = fake_board();
+        let chunk_size = board.shared_resource.section_mark.chunk_size();
+        let image_length = 4 * chunk_size;
+        board.hardware.flash.memory[REMAIN_OFFSET..REMAIN_OFFSET + image_length].fill(0);
+
+        let mut rx = vec![
+            Sof::Request as u8,
+            Command::StartUpdate as u8,
+            DEFAULT_CHUNK_BIT_IDX as u8,
+            START_UPDATE_FLAG_IMAGE_LENGTH,
+        ];
+        rx.extend_from_slice(&[0; 12]);
+        rx.extend_from_slice(&(image_length as u32).to_le_bytes());
+        rx.push(EOF_SIGNATURE);
+        // only the first chunk is sent, other three are omitted as erased
+        let mut chunk = vec![0u8; REASONABLE_RX_BUF];
+        let len = WriteChunkRequestForm::new_std(
+            0,
+            WRITE_CHUNK_FLAG_ACK,
+            REMAIN_OFFSET as u32,
+            &vec![0u8; chunk_size],
+            &mut chunk,
+        )
+        .unwrap_or_else(|_| panic!());
+        rx.extend_from_slice(&chunk[..len]);
+        // length other than the one of `StartUpdate` is refused, then the right one
+        for length in [image_length + chunk_size, image_length] {
+            rx.extend_from_slice(&[Sof::Request as u8, Command::Finalize as u8]);
+            rx.extend_from_slice(&(length as u32).to_le_bytes());
+            rx.push(EOF_SIGNATURE);
+        }
+
+        let tx = round_trip_on(&mut board, &rx);
+        let tx = &tx[core::mem::size_of::<StartUpdateResponseForm>()
+            + core::mem::size_of::<WriteChunkResponseForm>()..];
+        let (refused, finalized) = tx.split_at(core::mem::size_of::<FinalizeResponseForm>());
+
+        assert_eq!(refused[1], Command::Finalize as u8);
+        assert_eq!(refused[4], OtaError::InvalidArgument as u8);
+        assert_eq!(refused[6..8], 0u16.to_le_bytes());
+        // image without header passes as one from before headers existed
+        assert_eq!(finalized[4], OtaError::Nothing as u8);
+        assert_eq!(finalized[6..8], 3u16.to_le_bytes());
+        assert!(board.hardware.flash.memory
+            [REMAIN_OFFSET + chunk_size..REMAIN_OFFSET + image_length]
+            .iter()
+            .all(|&x| x == 0xFF));
+        for i in 0..4 {
+            let offset = REMAIN_OFFSET + i * chunk_size;
+            assert!(board.shared_resource.section_mark.is_marked(offset as u32));
+        }
+    }
+
+    /// Host tools predating chunk negotiation and v2 frames are answered as they always were
+    #[test]
+    fn baseline_v1_frames_get_baseline_responses() {
+        const CHUNK: usize = 256;
+        let mut board = fake_board();
+        let plain: Vec<u8> = (0..2 * CHUNK).map(|i| (i * 7) as u8).collect();
+        let mut encrypted = plain.clone();
+        ChaCha20::new(&CIPHER_KEY.into(), &SERIAL_NUMBER.into()).apply_keystream(&mut encrypted);
+
+        // Handshake, DeviceInfo, StartUpdate, two WriteChunk, UpdateStatus
+        let mut rx = vec![0xAA, 0x01, 0xFF, 0xAA, 0x02, 0xFF, 0xAA, 0x30, 0xFF];
+        for (i, chunk) in encrypted.chunks(CHUNK).enumerate() {
+            let mut body = ((REMAIN_OFFSET + i * CHUNK) as u32).to_le_bytes().to_vec();
+            body.extend_from_slice(chunk);
+
+            rx.extend_from_slice(&[0xAA, 0x40]);
+            rx.extend_from_slice(&(std_crc(&body) as u16).to_le_bytes());
+            rx.extend_from_slice(&body);
+            rx.push(0xFF);
+        }
+        rx.extend_from_slice(&[0xAA, 0xE0, 0xFF]);
+
+        let tx = round_trip_on(&mut board, &rx);
+
+        // checksummed response, `SOF | command | checksum | body | EOF`
+        let checksummed = |command: u8, body: &[u8]| {
+            let mut form = vec![0xBB, command];
+            form.extend_from_slice(&(std_crc(body) as u16).to_le_bytes());
+            form.extend_from_slice(body);
+            form.push(0xFF);
+            form
+        };
+        let mut info = vec![PROTOCOL_VERSION_V1, DEFAULT_CHUNK_BIT_IDX as u8];
+        info.extend_from_slice(&SERIAL_NUMBER);
+        // bit per 256 bytes chunk of application region, nothing after it
+        let mut bitmap = vec![0u8; REMAIN_SIZE / CHUNK / 8];
+        bitmap[0] = 0b11;
+
+        let mut expected = vec![0xBB, 0x01, 0xFF];
+        expected.extend(checksummed(0x02, &info));
+        expected.extend(checksummed(0x30, &SERIAL_NUMBER));
+        expected.extend_from_slice(&[0xBB, 0x40, 0x00, 0xFF, 0xBB, 0x40, 0x00, 0xFF]);
+        expected.extend(checksummed(0xE0, &bitmap));
+
+        assert_eq!(tx, expected);
+        assert_eq!(
+            board.hardware.flash.memory[REMAIN_OFFSET..REMAIN_OFFSET + 2 * CHUNK],
+            plain
+        );
+    }
+
+    fn set_baudrate(board: &mut Board<FakeHardware>, baudrate: u32) -> (Vec<u8>, u32) {
+        let mut rx = vec![Sof::Request as u8, Command::SetBaudRate as u8];
+        rx.extend_from_slice(&baudrate.to_le_bytes());
+        rx.push(EOF_SIGNATURE);
+        let mut transport = FakeTransport::new(&rx);
+        let mut service = OtaService::new();
+
+        while !transport.rx.is_empty() {
+            service.poll(board, &mut transport);
+        }
+
+        (transport.tx, transport.baudrate)
+    }
+
+    #[test]
+    fn reachable_baudrate_is_answered_then_applied() {
+        let (tx, baudrate) = set_baudrate(&mut fake_board(), 460800);
+
+        let mut expected = vec![Sof::Response as u8, Command::SetBaudRate as u8, 0x00];
+        expected.extend_from_slice(&460800_u32.to_le_bytes());
+        expected.push(EOF_SIGNATURE);
+        assert_eq!(tx, expected);
+        assert_eq!(baudrate, 460800);
+    }
+
+    #[test]
+    fn baudrate_too_far_from_clock_is_refused() {
+        // 16MHz / 17 is 2.1% off from 921600
+        let (tx, baudrate) = set_baudrate(&mut fake_board(), 921600);
+
+        let mut expected = vec![
+            Sof::Response as u8,
+            Command::SetBaudRate as u8,
+            OtaError::InvalidArgument as u8,
+        ];
+        expected.extend_from_slice(&[0; 4]);
+        expected.push(EOF_SIGNATURE);
+        assert_eq!(tx, expected);
+        assert_eq!(baudrate, DEFAULT_BAUDRATE);
+    }
+}
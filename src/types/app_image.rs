@@ -0,0 +1,209 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Inspection of installed application through its image header,
+//! header layout is shared with application by `laplus_boots_app::header`.
+
+pub use laplus_boots_app::header::{
+    AppHeader, APP_CONFIRM_MAGIC, APP_HEADER_MAGIC, APP_HEADER_OFFSET, APP_HEADER_SIZE,
+    APP_HEADER_VERSION,
+};
+
+use super::ota::OtaError;
+use super::section_mark::{REMAIN_OFFSET, REMAIN_SIZE};
+use crate::boards::{Checksum, Device, OtaFlash};
+
+/// Piece of image read at once for checksum, keeps stack usage small
+const READ_PIECE_SIZE: usize = 64;
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AppStatus {
+    Valid = 0,
+    /// Application region is erased
+    Empty = 1,
+    /// Application exist but there's no header
+    NoHeader = 2,
+    UnsupportedHeader = 3,
+    BadLength = 4,
+    CrcMismatch = 5,
+}
+
+#[derive(Clone, Copy)]
+pub struct AppInfo {
+    pub status: AppStatus,
+    pub confirmed: bool,
+    pub header: Option<AppHeader>,
+}
+
+impl AppInfo {
+    /// Inspect installed application, CRC peripheral is used for image checksum.
+    /// Application region that can't be read is taken as `BadLength`.
+    pub fn inspect<D: Device>(hardware: &mut D) -> Self {
+        Self::try_inspect(hardware).unwrap_or(Self::invalid(AppStatus::BadLength, None))
+    }
+
+    fn try_inspect<D: Device>(hardware: &mut D) -> Result<Self, OtaError> {
+        let flash = hardware.flash();
+
+        let mut first_word = [0u8; 4];
+        flash.read(REMAIN_OFFSET as u32, &mut first_word)?;
+        if first_word == [0xFF; 4] {
+            return Ok(Self::invalid(AppStatus::Empty, None));
+        }
+
+        let mut header = [0u8; APP_HEADER_SIZE];
+        flash.read((REMAIN_OFFSET + APP_HEADER_OFFSET) as u32, &mut header)?;
+        let header = AppHeader::from_bytes(&header);
+
+        if header.magic != APP_HEADER_MAGIC {
+            return Ok(Self::invalid(AppStatus::NoHeader, None));
+        }
+        if header.header_version != APP_HEADER_VERSION {
+            return Ok(Self::invalid(AppStatus::UnsupportedHeader, Some(header)));
+        }
+
+        let length = header.image_length();
+        if !(APP_HEADER_OFFSET + APP_HEADER_SIZE..=REMAIN_SIZE).contains(&length) {
+            return Ok(Self::invalid(AppStatus::BadLength, Some(header)));
+        }
+
+        hardware.crc().reset();
+        let mut actual = 0;
+        let mut buf = [0u8; READ_PIECE_SIZE];
+
+        for range in [
+            0..APP_HEADER_OFFSET,
+            APP_HEADER_OFFSET + APP_HEADER_SIZE..length,
+        ] {
+            for start in range.clone().step_by(READ_PIECE_SIZE) {
+                let piece = &mut buf[..(range.end - start).min(READ_PIECE_SIZE)];
+
+                hardware
+                    .flash()
+                    .read((REMAIN_OFFSET + start) as u32, piece)?;
+                actual = hardware.crc().feed_bytes(piece);
+            }
+        }
+
+        Ok(Self {
+            status: if actual == header.image_crc() {
+                AppStatus::Valid
+            } else {
+                AppStatus::CrcMismatch
+            },
+            confirmed: header.is_confirmed(),
+            header: Some(header),
+        })
+    }
+
+    const fn invalid(status: AppStatus, header: Option<AppHeader>) -> Self {
+        Self {
+            status,
+            confirmed: false,
+            header,
+        }
+    }
+
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.status == AppStatus::Valid
+    }
+
+    /// Application without header is from before the header existed, it can't be
+    /// verified but still allowed to boot.
+    #[inline]
+    pub fn is_bootable(&self) -> bool {
+        matches!(self.status, AppStatus::Valid | AppStatus::NoHeader)
+    }
+
+    /// Image with header still waits for confirmation from itself
+    #[inline]
+    pub fn is_trial(&self) -> bool {
+        self.is_valid() && !self.confirmed
+    }
+
+    /// Program `APP_CONFIRM_MAGIC` into header of inspected application.
+    /// `confirm` double-word can be programmed only once after erase.
+    pub fn confirm(&self, flash: &mut impl OtaFlash) -> Result<(), OtaError> {
+        let header = match (self.status, self.header) {
+            (AppStatus::Valid, Some(header)) => header,
+            _ => return Err(OtaError::InvalidArgument),
+        };
+
+        if header.is_confirmed() {
+            return Ok(());
+        } else if !header.is_confirmable() {
+            return Err(OtaError::InvalidArgument);
+        }
+
+        let offset = REMAIN_OFFSET + APP_HEADER_OFFSET + core::mem::offset_of!(AppHeader, confirm);
+        super::region::check_write(offset as u32, APP_CONFIRM_MAGIC.len())?;
+        flash.write(offset as u32, &APP_CONFIRM_MAGIC)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use laplus_boots_app::header::compute_image_crc;
+
+    use super::*;
+    use crate::boards::fake::FakeHardware;
+
+    /// Image as the packer leaves it, header filled with its length and CRC
+    fn install(hardware: &mut FakeHardware, length: usize) {
+        let mut image: Vec<u8> = (0..length).map(|i| (i * 13) as u8).collect();
+        let header = AppHeader::new(*b"0.1.0\0\0\0");
+        let header_bytes = unsafe {
+            core::slice::from_raw_parts(&header as *const AppHeader as *const u8, APP_HEADER_SIZE)
+        };
+        let header_range = APP_HEADER_OFFSET..APP_HEADER_OFFSET + APP_HEADER_SIZE;
+        image[header_range.clone()].copy_from_slice(header_bytes);
+        image[APP_HEADER_OFFSET + 8..APP_HEADER_OFFSET + 12]
+            .copy_from_slice(&(length as u32).to_le_bytes());
+        let crc = compute_image_crc(&image);
+        image[APP_HEADER_OFFSET + 12..APP_HEADER_OFFSET + 16].copy_from_slice(&crc.to_le_bytes());
+
+        hardware.flash.memory[REMAIN_OFFSET..REMAIN_OFFSET + length].copy_from_slice(&image);
+    }
+
+    #[test]
+    fn packed_image_is_valid_trial() {
+        let mut hardware = FakeHardware::new();
+        install(&mut hardware, 0x300);
+
+        let info = AppInfo::inspect(&mut hardware);
+
+        assert!(info.is_valid());
+        assert!(info.is_trial());
+    }
+
+    #[test]
+    fn corrupted_image_is_crc_mismatch() {
+        let mut hardware = FakeHardware::new();
+        install(&mut hardware, 0x300);
+        hardware.flash.memory[REMAIN_OFFSET + 0x2FF] ^= 1;
+
+        let info = AppInfo::inspect(&mut hardware);
+
+        assert!(info.status == AppStatus::CrcMismatch);
+        assert!(!info.is_bootable());
+    }
+
+    #[test]
+    fn confirmed_image_stays_valid() {
+        let mut hardware = FakeHardware::new();
+        install(&mut hardware, 0x300);
+
+        let info = AppInfo::inspect(&mut hardware);
+        assert!(info.confirm(&mut hardware.flash).is_ok());
+
+        let info = AppInfo::inspect(&mut hardware);
+        assert!(info.is_valid());
+        assert!(info.confirmed);
+        assert!(!info.is_trial());
+    }
+}
@@ -0,0 +1,86 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Why the bootloader stayed resident instead of jumping to application,
+//! reported with `Diagnostics` command.
+
+#[cfg(target_arch = "arm")]
+use embassy_stm32::pac::RCC;
+use laplus_boots_app::Handoff;
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BootReason {
+    /// Application jumped into bootloader with `BOOTLOADER_KEY`
+    BootParam = 1,
+    /// `force_bootloader` pin was held during boot
+    ForcePin = 2,
+    /// Application region is empty or its image is broken
+    InvalidApp = 3,
+    /// Application requested OTA through the RAM mailbox
+    Mailbox = 4,
+    /// Host held UART RX line in break (low) during boot
+    RxBreak = 5,
+    /// Host sent `ENTRY_MAGIC` right after reset
+    EntryMagic = 6,
+}
+
+/// RCC CSR reset flags at boot
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ResetFlags(pub u8);
+
+impl ResetFlags {
+    // same bits are handed off to application
+    pub const PIN: u8 = Handoff::RESET_PIN;
+    /// Brown-out or power-on reset
+    pub const POWER: u8 = Handoff::RESET_POWER;
+    pub const SOFTWARE: u8 = Handoff::RESET_SOFTWARE;
+    pub const INDEPENDENT_WATCHDOG: u8 = Handoff::RESET_INDEPENDENT_WATCHDOG;
+    pub const WINDOW_WATCHDOG: u8 = Handoff::RESET_WINDOW_WATCHDOG;
+    pub const LOW_POWER: u8 = Handoff::RESET_LOW_POWER;
+    pub const OPTION_BYTE_LOADER: u8 = Handoff::RESET_OPTION_BYTE_LOADER;
+
+    /// Read reset cause, flags are left in RCC for application to read and clear
+    #[cfg(target_arch = "arm")]
+    pub fn read() -> Self {
+        let csr = RCC.csr().read();
+        let mut ret = 0;
+
+        if csr.pinrstf() {
+            ret |= Self::PIN;
+        }
+        if csr.pwrrstf() {
+            ret |= Self::POWER;
+        }
+        if csr.sftrstf() {
+            ret |= Self::SOFTWARE;
+        }
+        if csr.iwdgrstf() {
+            ret |= Self::INDEPENDENT_WATCHDOG;
+        }
+        if csr.wwdgrstf() {
+            ret |= Self::WINDOW_WATCHDOG;
+        }
+        if csr.lpwrrstf() {
+            ret |= Self::LOW_POWER;
+        }
+        if csr.oblrstf() {
+            ret |= Self::OPTION_BYTE_LOADER;
+        }
+
+        Self(ret)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct BootDiagnostics {
+    pub reason: BootReason,
+    pub reset_flags: ResetFlags,
+    /// raw r0 value received on entry
+    pub boot_param: u32,
+    /// raw `MailboxCommand` taken on entry, 0 when there was none
+    pub mailbox_command: u8,
+}
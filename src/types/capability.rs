@@ -0,0 +1,88 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Protocol versions and optional features announced on `Handshake`.
+//! Bits are never reused, a bootloader without a bit must be treated as
+//! not supporting the feature.
+
+use super::frame::{PROTOCOL_VERSION_V1, PROTOCOL_VERSION_V2};
+
+/// Bitmask of protocol versions, bit `n` stands for version `n`
+pub const SUPPORTED_VERSIONS: u8 = (1 << PROTOCOL_VERSION_V1) | (1 << PROTOCOL_VERSION_V2);
+
+/// Chunk size is negotiated on `StartUpdate`
+pub const CAP_VARIABLE_CHUNK: u32 = 1 << 0;
+/// `SetBaudRate` command
+pub const CAP_SET_BAUDRATE: u32 = 1 << 1;
+/// Sequence numbers and windowed `WriteChunk` streaming
+pub const CAP_WINDOWED_WRITE: u32 = 1 << 2;
+/// Explicit flash erase (reserved)
+#[allow(unused)]
+pub const CAP_ERASE: u32 = 1 << 3;
+/// Compressed chunk payload (reserved)
+#[allow(unused)]
+pub const CAP_COMPRESSION: u32 = 1 << 4;
+/// Signed image authentication (reserved)
+#[allow(unused)]
+pub const CAP_AUTH: u32 = 1 << 5;
+
+/// `MemoryMap` command for flash layout and buffer limits
+pub const CAP_MEMORY_MAP: u32 = 1 << 6;
+
+/// `BootloaderInfo` command for build identity
+pub const CAP_BOOTLOADER_INFO: u32 = 1 << 7;
+/// `AppInfo` command for installed application header
+pub const CAP_APP_INFO: u32 = 1 << 8;
+/// `Nack` response on rejected request frames
+pub const CAP_NACK: u32 = 1 << 9;
+/// `Diagnostics` command for boot reason and reset flags
+pub const CAP_DIAGNOSTICS: u32 = 1 << 10;
+/// Frames prefixed with device address are answered
+pub const CAP_ADDRESSED_FRAME: u32 = 1 << 11;
+/// Half-duplex RS-485 build, only addressed frames are answered
+pub const CAP_RS485: u32 = 1 << 12;
+/// Broadcast address, host nonce on `StartUpdate` and unsequenced `WriteChunk`
+pub const CAP_BROADCAST: u32 = 1 << 13;
+/// Host link is I2C target, response is polled by host read
+pub const CAP_I2C_TARGET: u32 = 1 << 14;
+/// `OptionBytes` and `SetProtection` commands for WRP and RDP
+pub const CAP_OPTION_BYTES: u32 = 1 << 15;
+/// `StartUpdate` with image length and `Finalize`, erased chunks may be omitted
+pub const CAP_SPARSE_TRANSFER: u32 = 1 << 16;
+
+/// Capabilities of this bootloader build
+pub const CAPABILITIES: u32 = CAP_VARIABLE_CHUNK
+    | CAP_WINDOWED_WRITE
+    | CAP_MEMORY_MAP
+    | CAP_BOOTLOADER_INFO
+    | CAP_APP_INFO
+    | CAP_NACK
+    | CAP_DIAGNOSTICS
+    | CAP_ADDRESSED_FRAME
+    | CAP_BROADCAST
+    | CAP_OPTION_BYTES
+    | CAP_SPARSE_TRANSFER
+    | BUILD_CAPABILITIES;
+
+/// Capabilities depending on build features, I2C has no baudrate to change
+const BUILD_CAPABILITIES: u32 = if cfg!(feature = "rs485") {
+    CAP_RS485 | CAP_SET_BAUDRATE
+} else if cfg!(feature = "host_i2c") {
+    CAP_I2C_TARGET
+} else {
+    CAP_SET_BAUDRATE
+};
+
+/// Highest version both side support, `None` when there's nothing in common
+pub const fn negotiate_version(host_versions: u8) -> Option<u8> {
+    let common = host_versions & SUPPORTED_VERSIONS;
+
+    if common == 0 {
+        None
+    } else {
+        Some(7 - common.leading_zeros() as u8)
+    }
+}
@@ -0,0 +1,216 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Protocol framing, v1 and v2 frames carry the same `ota` forms.
+//!
+//! v1 frame is the form itself, `SOF | command | body | EOF`.
+//!
+//! v2 frame wraps the form body with length, sequence and full CRC32,
+//! ```text
+//! SOF(0xA5/0xB5) | version(0x02) | length(u16 le) | sequence(u16 le) | command
+//!   | payload(length) | CRC32(u32 le, from SOF to end of payload)
+//! ```
+//! `payload` is exactly the v1 form between command and EOF (including its own
+//! checksum field if exist), so both versions share one set of forms.
+//! Response is sent in the same version as request, with echoed sequence.
+//!
+//! On multi-drop bus either frame is prefixed with device address,
+//! ```text
+//! SOF(0xAD/0xBD) | address | v1 or v2 frame
+//! ```
+//! response carries the address of the answering device.
+//! Frames to `BROADCAST_ADDRESS` are taken by every device and never answered.
+
+use super::ota::{OtaError, Sof, EOF_SIGNATURE};
+use crate::boards::Checksum;
+
+pub const PROTOCOL_VERSION_V1: u8 = 0x01;
+pub const PROTOCOL_VERSION_V2: u8 = 0x02;
+
+pub const SOF_V2_REQUEST: u8 = 0xA5;
+pub const SOF_V2_RESPONSE: u8 = 0xB5;
+
+pub const SOF_ADDRESSED_REQUEST: u8 = 0xAD;
+pub const SOF_ADDRESSED_RESPONSE: u8 = 0xBD;
+/// sof + address
+pub const ADDRESS_PREFIX_LEN: usize = 2;
+/// Every device on bus takes it, none of them answers
+pub const BROADCAST_ADDRESS: u8 = 0xFF;
+
+/// sof + version + length + sequence + command
+pub const V2_HEADER_LEN: usize = 1 + 1 + 2 + 2 + 1;
+pub const V2_CRC_LEN: usize = 4;
+/// v1 form position in v2 frame, right before the payload
+const V2_FORM_OFFSET: usize = V2_HEADER_LEN - 2;
+/// v1 form is written at this offset of tx buffer, so it can be wrapped as v2
+/// and prefixed with address in place
+pub const RESPONSE_FORM_OFFSET: usize = ADDRESS_PREFIX_LEN + V2_FORM_OFFSET;
+/// Extra bytes of v2 frame compared with v1 form (sof, command and eof are reused)
+pub const V2_OVERHEAD: usize = V2_HEADER_LEN + V2_CRC_LEN - 3;
+/// Extra bytes of the largest frame compared with v1 form
+pub const MAX_FRAME_OVERHEAD: usize = ADDRESS_PREFIX_LEN + V2_OVERHEAD;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Envelope {
+    V1,
+    V2 { sequence: u16 },
+}
+
+/// Length of v2 frame at the beginning of `head`,
+/// `OutOfRange` when header is not fully received yet
+pub fn v2_frame_len(head: &[u8]) -> Result<usize, OtaError> {
+    if head.len() < V2_HEADER_LEN {
+        return Err(OtaError::OutOfRange);
+    }
+    if head[1] != PROTOCOL_VERSION_V2 {
+        return Err(OtaError::UnsupportedVersion);
+    }
+
+    let length = u16::from_le_bytes([head[2], head[3]]) as usize;
+    let frame_len = V2_HEADER_LEN + length + V2_CRC_LEN;
+
+    if head.len() < frame_len {
+        Err(OtaError::OutOfRange)
+    } else {
+        Ok(frame_len)
+    }
+}
+
+/// Length of address prefixed frame at the beginning of `head`,
+/// `inner_len` tells the length of the frame behind the prefix
+pub fn addressed_frame_len(
+    head: &[u8],
+    inner_len: impl FnOnce(&[u8]) -> Result<usize, OtaError>,
+) -> Result<usize, OtaError> {
+    if head.len() < ADDRESS_PREFIX_LEN + 1 {
+        return Err(OtaError::OutOfRange);
+    }
+    if head[ADDRESS_PREFIX_LEN] == SOF_ADDRESSED_REQUEST {
+        return Err(OtaError::MissingSof);
+    }
+
+    Ok(ADDRESS_PREFIX_LEN + inner_len(&head[ADDRESS_PREFIX_LEN..])?)
+}
+
+/// Split address prefix from frame, `None` when frame is not addressed
+pub fn split_address(frame: &mut [u8]) -> (Option<u8>, &mut [u8]) {
+    match frame {
+        [SOF_ADDRESSED_REQUEST, address, inner @ ..] => (Some(*address), inner),
+        _ => (None, frame),
+    }
+}
+
+/// Bus address derived from serial number when nothing else is given,
+/// `0x00` and `BROADCAST_ADDRESS` are kept reserved
+pub const fn derive_bus_address(serial_number: &[u8; 12]) -> u8 {
+    let mut ret = 0u8;
+    let mut i = 0;
+    while i < serial_number.len() {
+        ret = ret.rotate_left(3) ^ serial_number[i];
+        i += 1;
+    }
+
+    match ret {
+        0x00 => 0x01,
+        0xFF => 0xFE,
+        x => x,
+    }
+}
+
+impl Envelope {
+    /// Envelope and command byte of a frame before it's checked, to answer it on rejection
+    pub fn peek(frame: &[u8]) -> (Self, Option<u8>) {
+        match frame.first().copied() {
+            Some(SOF_V2_REQUEST) if frame.len() >= V2_HEADER_LEN => (
+                Self::V2 {
+                    sequence: u16::from_le_bytes([frame[4], frame[5]]),
+                },
+                Some(frame[6]),
+            ),
+            _ => (Self::V1, frame.get(1).copied()),
+        }
+    }
+
+    /// Check frame and turn it into v1 request form in place
+    pub fn open<'a>(
+        frame: &'a mut [u8],
+        crc: &mut impl Checksum,
+    ) -> Result<(Self, &'a [u8]), OtaError> {
+        match frame.first().copied() {
+            Some(sof) if sof == Sof::Request as u8 => Ok((Self::V1, frame)),
+            Some(SOF_V2_REQUEST) => {
+                let frame_len = v2_frame_len(frame)?;
+                let crc_pos = frame_len - V2_CRC_LEN;
+
+                crc.reset();
+                let actual = crc.feed_bytes(&frame[..crc_pos]);
+                let expected = u32::from_le_bytes([
+                    frame[crc_pos],
+                    frame[crc_pos + 1],
+                    frame[crc_pos + 2],
+                    frame[crc_pos + 3],
+                ]);
+                if actual != expected {
+                    return Err(OtaError::ChecksumError);
+                }
+
+                let sequence = u16::from_le_bytes([frame[4], frame[5]]);
+
+                // SOF | command | payload | EOF, right before the payload
+                frame[V2_FORM_OFFSET] = Sof::Request as u8;
+                frame[crc_pos] = EOF_SIGNATURE;
+
+                Ok((Self::V2 { sequence }, &frame[V2_FORM_OFFSET..=crc_pos]))
+            }
+            _ => Err(OtaError::MissingSof),
+        }
+    }
+
+    /// Wrap v1 response form written at `tx_buf[RESPONSE_FORM_OFFSET..]`,
+    /// prefixed with `address` when it's given. Returns bytes to transmit.
+    pub fn seal<'a>(
+        &self,
+        tx_buf: &'a mut [u8],
+        form_len: usize,
+        address: Option<u8>,
+        crc: &mut impl Checksum,
+    ) -> &'a [u8] {
+        let (start, end) = match *self {
+            Self::V1 => (RESPONSE_FORM_OFFSET, RESPONSE_FORM_OFFSET + form_len),
+            Self::V2 { sequence } => {
+                let frame = &mut tx_buf[ADDRESS_PREFIX_LEN..];
+                let length = form_len - 3;
+                let crc_pos = V2_HEADER_LEN + length;
+
+                frame[0] = SOF_V2_RESPONSE;
+                frame[1] = PROTOCOL_VERSION_V2;
+                frame[2..4].copy_from_slice(&(length as u16).to_le_bytes());
+                frame[4..6].copy_from_slice(&sequence.to_le_bytes());
+                // frame[6] is command of the form
+
+                crc.reset();
+                let checksum = crc.feed_bytes(&frame[..crc_pos]);
+                frame[crc_pos..crc_pos + V2_CRC_LEN].copy_from_slice(&checksum.to_le_bytes());
+
+                (
+                    ADDRESS_PREFIX_LEN,
+                    ADDRESS_PREFIX_LEN + crc_pos + V2_CRC_LEN,
+                )
+            }
+        };
+
+        match address {
+            Some(address) => {
+                let start = start - ADDRESS_PREFIX_LEN;
+                tx_buf[start] = SOF_ADDRESSED_RESPONSE;
+                tx_buf[start + 1] = address;
+
+                &tx_buf[start..end]
+            }
+            None => &tx_buf[start..end],
+        }
+    }
+}
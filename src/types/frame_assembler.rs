@@ -0,0 +1,281 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Reassemble request frames out of arbitrary sized UART reads.
+//! Received bytes are kept in a ring buffer, partial frames stay until the rest
+//! arrives, garbage in front of a frame is skipped by scanning for start byte.
+//! Knowledge of the frame itself is given through [`FrameFormat`],
+//! so this module has no dependency to hardware.
+
+use super::ota::OtaError;
+
+pub trait FrameFormat {
+    /// Whether a frame could begin with this byte
+    fn is_start(&self, byte: u8) -> bool;
+
+    /// Whether a frame could end with this byte
+    fn is_end(&self, byte: u8) -> bool;
+
+    /// Length of frame starting at `head[0]`.
+    /// `OutOfRange` when more bytes are needed, any other error means
+    /// `head[0]` is not a beginning of valid frame.
+    fn frame_len(&self, head: &[u8]) -> Result<usize, OtaError>;
+
+    /// Command byte of frame starting at `head[0]`, if it's already received
+    fn command_of(&self, head: &[u8]) -> Option<u8>;
+}
+
+/// What the last [`FrameAssembler::pop`] discarded
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rejected {
+    /// Nothing, or noise which doesn't look like a frame at all
+    Nothing,
+    /// Bytes ending like a frame without any start byte, it got lost on the line
+    Headless,
+    /// Frame beginning with start byte, with its command byte if received.
+    /// Head of the frame is left in `out` of `pop`.
+    Frame(Option<u8>),
+}
+
+pub struct FrameAssembler<const N: usize> {
+    ring: [u8; N],
+    /// index of the oldest byte
+    head: usize,
+    len: usize,
+    rejected: Rejected,
+    /// bytes after a rejected start byte are rest of that frame, not another one
+    resyncing: bool,
+}
+
+impl<const N: usize> FrameAssembler<N> {
+    pub const fn new() -> Self {
+        Self {
+            ring: [0; N],
+            head: 0,
+            len: 0,
+            rejected: Rejected::Nothing,
+            resyncing: false,
+        }
+    }
+
+    /// What the last [`Self::pop`] discarded
+    #[inline]
+    pub const fn rejected(&self) -> Rejected {
+        self.rejected
+    }
+
+    /// Drop every byte, e.g. partial frame is left too long
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.resyncing = false;
+    }
+
+    /// Store received bytes, oldest bytes are discarded when ring buffer is full
+    pub fn push(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if self.len == N {
+                self.skip(1);
+            }
+            self.ring[(self.head + self.len) % N] = b;
+            self.len += 1;
+        }
+    }
+
+    fn skip(&mut self, n: usize) {
+        let n = n.min(self.len);
+        self.head = (self.head + n) % N;
+        self.len -= n;
+    }
+
+    /// Copy stored bytes into `out` as contiguous slice, returns copied length
+    fn peek(&self, out: &mut [u8]) -> usize {
+        let n = self.len.min(out.len());
+        let first = n.min(N - self.head);
+
+        out[..first].copy_from_slice(&self.ring[self.head..self.head + first]);
+        out[first..n].copy_from_slice(&self.ring[..n - first]);
+
+        n
+    }
+
+    /// Take next complete frame into `out`.
+    ///
+    /// `Err(OutOfRange)` means there's no complete frame yet, keep pushing.
+    /// Other errors report the reason bytes were discarded, `MissingSof` for
+    /// garbage before start byte. Call again since more frame could be left.
+    /// [`Self::rejected`] tells whether the discarded bytes were worth answering.
+    pub fn pop<'b, F: FrameFormat>(
+        &mut self,
+        format: &F,
+        out: &'b mut [u8],
+    ) -> Result<&'b mut [u8], OtaError> {
+        self.rejected = Rejected::Nothing;
+
+        let mut garbage = 0;
+        while garbage < self.len && !format.is_start(self.ring[(self.head + garbage) % N]) {
+            garbage += 1;
+        }
+        if garbage != 0 {
+            // a lone byte is rather a glitch on the line than a frame
+            let last = self.ring[(self.head + garbage - 1) % N];
+            if !self.resyncing && garbage > 1 && format.is_end(last) {
+                self.rejected = Rejected::Headless;
+            }
+            self.resyncing = false;
+            self.skip(garbage);
+            return Err(OtaError::MissingSof);
+        }
+        if self.len == 0 {
+            return Err(OtaError::OutOfRange);
+        }
+
+        let available = self.peek(out);
+
+        match format.frame_len(&out[..available]) {
+            Ok(frame_len) if frame_len > out.len() => {
+                self.reject(format.command_of(&out[..available]));
+                Err(OtaError::MissingEof)
+            }
+            Ok(frame_len) if frame_len <= available => {
+                self.resyncing = false;
+                self.skip(frame_len);
+                Ok(&mut out[..frame_len])
+            }
+            Ok(_) | Err(OtaError::OutOfRange) if self.len < N.min(out.len()) => {
+                Err(OtaError::OutOfRange)
+            }
+            // cannot grow anymore but still incomplete, the start byte was a fake one
+            Ok(_) | Err(OtaError::OutOfRange) => {
+                self.reject(format.command_of(&out[..available]));
+                Err(OtaError::MissingEof)
+            }
+            Err(e) => {
+                self.reject(format.command_of(&out[..available]));
+                Err(e)
+            }
+        }
+    }
+
+    /// Drop start byte of rejected frame, search next one from the byte after it
+    fn reject(&mut self, command: Option<u8>) {
+        self.rejected = Rejected::Frame(command);
+        self.resyncing = true;
+        self.skip(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ota::{Command, RequestFrame, Sof, EOF_SIGNATURE};
+
+    const FORMAT: RequestFrame = RequestFrame {
+        chunk_size: 64,
+        legacy_transfer: false,
+    };
+    const SOF: u8 = Sof::Request as u8;
+    const DEVICE_INFO: [u8; 3] = [SOF, Command::DeviceInfo as u8, EOF_SIGNATURE];
+
+    fn pop<const N: usize>(assembler: &mut FrameAssembler<N>) -> Result<Vec<u8>, OtaError> {
+        let mut out = [0u8; 64];
+
+        assembler.pop(&FORMAT, &mut out).map(|frame| frame.to_vec())
+    }
+
+    #[test]
+    fn garbage_before_sof_is_skipped() {
+        let mut assembler = FrameAssembler::<64>::new();
+        assembler.push(&[0x00, 0x12, 0x34]);
+        assembler.push(&DEVICE_INFO);
+
+        assert_eq!(pop(&mut assembler), Err(OtaError::MissingSof));
+        assert_eq!(pop(&mut assembler), Ok(DEVICE_INFO.to_vec()));
+        assert_eq!(pop(&mut assembler), Err(OtaError::OutOfRange));
+    }
+
+    #[test]
+    fn frame_split_across_pushes() {
+        let mut assembler = FrameAssembler::<64>::new();
+
+        for &b in &DEVICE_INFO[..2] {
+            assembler.push(&[b]);
+            assert_eq!(pop(&mut assembler), Err(OtaError::OutOfRange));
+        }
+        assembler.push(&DEVICE_INFO[2..]);
+
+        assert_eq!(pop(&mut assembler), Ok(DEVICE_INFO.to_vec()));
+    }
+
+    #[test]
+    fn fake_sof_in_garbage_before_frame() {
+        let mut assembler = FrameAssembler::<64>::new();
+        assembler.push(&[0x11, SOF, 0x99]);
+        assembler.push(&DEVICE_INFO);
+
+        assert_eq!(pop(&mut assembler), Err(OtaError::MissingSof));
+        assert_eq!(assembler.rejected(), Rejected::Nothing);
+        assert_eq!(pop(&mut assembler), Err(OtaError::UnknownCommand));
+        assert_eq!(assembler.rejected(), Rejected::Frame(Some(0x99)));
+        assert_eq!(pop(&mut assembler), Err(OtaError::MissingSof));
+        assert_eq!(assembler.rejected(), Rejected::Nothing);
+        assert_eq!(pop(&mut assembler), Ok(DEVICE_INFO.to_vec()));
+    }
+
+    #[test]
+    fn missing_eof_resyncs_to_next_sof() {
+        let mut assembler = FrameAssembler::<64>::new();
+        assembler.push(&[SOF, Command::DeviceInfo as u8, 0x00]);
+        assembler.push(&DEVICE_INFO);
+
+        assert_eq!(pop(&mut assembler), Err(OtaError::MissingEof));
+        assert_eq!(
+            assembler.rejected(),
+            Rejected::Frame(Some(Command::DeviceInfo as u8))
+        );
+        // rest of the rejected frame is not answered again
+        assert_eq!(pop(&mut assembler), Err(OtaError::MissingSof));
+        assert_eq!(assembler.rejected(), Rejected::Nothing);
+        assert_eq!(pop(&mut assembler), Ok(DEVICE_INFO.to_vec()));
+        assert_eq!(pop(&mut assembler), Err(OtaError::OutOfRange));
+    }
+
+    #[test]
+    fn frame_without_sof_is_headless() {
+        let mut assembler = FrameAssembler::<64>::new();
+        assembler.push(&DEVICE_INFO[1..]);
+        assembler.push(&DEVICE_INFO);
+
+        assert_eq!(pop(&mut assembler), Err(OtaError::MissingSof));
+        assert_eq!(assembler.rejected(), Rejected::Headless);
+        assert_eq!(pop(&mut assembler), Ok(DEVICE_INFO.to_vec()));
+
+        // lone end byte is a glitch rather than a frame
+        assembler.push(&[EOF_SIGNATURE]);
+        assert_eq!(pop(&mut assembler), Err(OtaError::MissingSof));
+        assert_eq!(assembler.rejected(), Rejected::Nothing);
+    }
+
+    #[test]
+    fn full_ring_buffer_keeps_newest_bytes() {
+        let mut assembler = FrameAssembler::<8>::new();
+        // wraps around, only last 8 bytes are left
+        assembler.push(&[0x00; 7]);
+        assembler.push(&DEVICE_INFO);
+
+        assert_eq!(pop(&mut assembler), Err(OtaError::MissingSof));
+        assert_eq!(pop(&mut assembler), Ok(DEVICE_INFO.to_vec()));
+
+        // start of frame longer than ring buffer can never complete
+        let mut write_chunk = vec![SOF, Command::WriteChunk as u8];
+        write_chunk.resize(8, 0x00);
+        assembler.push(&write_chunk);
+
+        assert_eq!(pop(&mut assembler), Err(OtaError::MissingEof));
+        assert_eq!(pop(&mut assembler), Err(OtaError::MissingSof));
+        assert_eq!(pop(&mut assembler), Err(OtaError::OutOfRange));
+    }
+}
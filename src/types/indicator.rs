@@ -0,0 +1,52 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Status LED patterns, so the state can be told without console.
+//! Patterns are derived from free running clock, nothing has to be scheduled.
+//!
+//! | Indicator      | Single LED             | Status LED / Error LED |
+//! |----------------|------------------------|------------------------|
+//! | `WaitingHost`  | 1Hz even blink         | 1Hz blink / off        |
+//! | `Receiving`    | 5Hz even blink         | 5Hz blink / off        |
+//! | `VerifyFailed` | double flash per 1s    | 1Hz blink / on         |
+//! | `InvalidApp`   | single flash per 2s    | 1Hz blink / 1Hz blink  |
+//! | `Done`         | on                     | on / off               |
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Indicator {
+    /// Bootloader is resident, host hasn't started update
+    WaitingHost = 0,
+    /// Chunks are being written
+    Receiving = 1,
+    /// Chunk couldn't be programmed or image check failed after update
+    VerifyFailed = 2,
+    /// Stayed in bootloader because application is missing or broken
+    InvalidApp = 3,
+    /// Updated image passed the check, waiting reset or jump
+    Done = 4,
+}
+
+impl Indicator {
+    /// Level of status LED and error LED at `ms` of uptime.
+    /// Without error LED, failures are told by status LED alone.
+    pub fn levels(self, ms: u64, has_error_led: bool) -> (bool, bool) {
+        let waiting = (ms % 1000) < 500;
+
+        match (self, has_error_led) {
+            (Self::WaitingHost, _) => (waiting, false),
+            (Self::Receiving, _) => ((ms % 200) < 100, false),
+            (Self::Done, _) => (true, false),
+            (Self::VerifyFailed, true) => (waiting, true),
+            (Self::VerifyFailed, false) => {
+                let phase = ms % 1000;
+                (phase < 100 || (200..300).contains(&phase), false)
+            }
+            (Self::InvalidApp, true) => (waiting, waiting),
+            (Self::InvalidApp, false) => ((ms % 2000) < 100, false),
+        }
+    }
+}
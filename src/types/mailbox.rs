@@ -0,0 +1,39 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Mailbox shared with application across reset, it takes the last 16 bytes
+//! of RAM. build.rs moves `_stack_start` below it, application has to do the
+//! same in its own build. Format is defined by `laplus_boots_app::mailbox`.
+
+pub use laplus_boots_app::mailbox::{Handoff, Mailbox, MailboxCommand};
+use static_assertions::const_assert_eq;
+
+// size must match `MAILBOX_RESERVED` of build.rs
+const_assert_eq!(laplus_boots_app::MAILBOX_SIZE, 16);
+const_assert_eq!(
+    laplus_boots_app::MAILBOX_ADDRESS,
+    env_to_array::hex_env_to_usize!("RAM_ORIGIN") + env_to_array::hex_env_to_usize!("RAM_LENGTH")
+        - laplus_boots_app::MAILBOX_SIZE
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::std_crc::std_crc;
+
+    /// Mailbox written by application build is accepted with the CRC packets use
+    #[test]
+    fn mailbox_crc_is_packet_crc() {
+        let mailbox = Mailbox::new(MailboxCommand::EnterOta, 0x42);
+        let mut src = [0u8; 12];
+        src[0..4].copy_from_slice(&mailbox.magic.to_le_bytes());
+        src[4..8].copy_from_slice(&mailbox.command.to_le_bytes());
+        src[8..12].copy_from_slice(&mailbox.argument.to_le_bytes());
+
+        assert_eq!(mailbox.crc, std_crc(&src));
+        assert!(mailbox.command() == Some(MailboxCommand::EnterOta));
+    }
+}
@@ -4,29 +4,28 @@
  * SPDX-License-Identifier: MIT OR Apache-2.0
  */
 
-pub const CRC_POLY_INIT: u32 = 0xA097;
-pub const BOOTLOADER_KEY: u32 = 0xB00710AD; // BOOTLOAD
+pub use laplus_boots_app::{BOOTLOADER_KEY, CRC_POLY_INIT};
 
+pub mod app_image;
+pub mod boot_reason;
+pub mod capability;
 pub mod const_convert;
+pub mod frame;
+pub mod frame_assembler;
+pub mod indicator;
+pub mod mailbox;
+pub mod option_bytes;
 pub mod ota;
+pub mod region;
 pub mod section_mark;
+pub mod write_window;
 
 // #[cfg(any(not(feature = "no_std"), feature = "std", test))]
 pub(crate) mod std_crc;
 
-#[inline]
-#[allow(unused)]
-unsafe fn __jump_to_bootloader(param: u32) -> ! {
-    core::arch::asm!("mov r0, {0}", in(reg) param); // must be insure r0 is keep until bootstrap
-
-    cortex_m::asm::bootload(section_mark::BOOTLOADER_ORIGIN as *const u32)
-}
-
-#[allow(unused)]
-pub unsafe fn jump_to_bootloader() -> ! {
-    __jump_to_bootloader(BOOTLOADER_KEY)
-}
+// `jump_to_bootloader` and mailbox requests for application are in `laplus_boots_app`
 
+#[cfg(target_arch = "arm")]
 pub unsafe fn read_bootloader_param() -> u32 {
     let param: u32;
     // must be insure r0 is keep after bootstrap
@@ -37,6 +36,7 @@ pub unsafe fn read_bootloader_param() -> u32 {
     param
 }
 
+#[cfg(target_arch = "arm")]
 pub unsafe fn jump_to_app() -> ! {
     #[allow(unused_mut)]
     let mut p = cortex_m::Peripherals::steal();
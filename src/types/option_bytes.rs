@@ -0,0 +1,179 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! FLASH option bytes, write protection (WRP) over the bootloader and
+//! readout protection (RDP) as the last step of production.
+//! embassy-stm32 doesn't touch option bytes, registers are driven through PAC,
+//! only [`OptionBytes`] itself is built for host tests.
+//! Programmed values take effect on option byte reload, which resets the MCU.
+
+#[cfg(target_arch = "arm")]
+use embassy_stm32::pac::flash::regs::Sr;
+#[cfg(target_arch = "arm")]
+use embassy_stm32::pac::flash::vals::Rdp;
+#[cfg(target_arch = "arm")]
+use embassy_stm32::pac::FLASH;
+
+#[cfg(target_arch = "arm")]
+use super::ota::OtaError;
+use super::region::PAGE_SIZE;
+use super::section_mark::{BOOTLOADER_LENGTH, BOOTLOADER_ORIGIN, FLASH_BASE};
+
+const FLASH_KEY1: u32 = 0x4567_0123;
+const FLASH_KEY2: u32 = 0xCDEF_89AB;
+const OPT_KEY1: u32 = 0x0819_2A3B;
+const OPT_KEY2: u32 = 0x4C5D_6E7F;
+
+/// FLASH_SR BSY1
+const SR_BSY: u32 = 1 << 16;
+/// FLASH_SR OPERR, PROGERR, WRPERR, PGAERR, SIZERR, PGSERR, MISSERR, FASTERR and OPTVERR
+const SR_ERRORS: u32 = 0x0000_83FA;
+
+const RDP_LEVEL0: u8 = 0xAA;
+const RDP_LEVEL2: u8 = 0xCC;
+/// Any value but level 0 and level 2 is level 1
+const RDP_LEVEL1: u8 = 0xBB;
+
+const BOOTLOADER_FIRST_PAGE: u8 = ((BOOTLOADER_ORIGIN - FLASH_BASE) / PAGE_SIZE) as u8;
+const BOOTLOADER_LAST_PAGE: u8 =
+    ((BOOTLOADER_ORIGIN - FLASH_BASE + BOOTLOADER_LENGTH) / PAGE_SIZE - 1) as u8;
+
+/// Write protect pages of the bootloader with WRP area A
+pub const PROTECT_FLAG_WRP_BOOTLOADER: u8 = 0x01;
+/// Readout protection level 1, going back to level 0 mass-erases the flash.
+/// Level 2 is never applied, it can't be undone.
+pub const PROTECT_FLAG_RDP_LEVEL1: u8 = 0x02;
+pub const PROTECT_FLAGS: u8 = PROTECT_FLAG_WRP_BOOTLOADER | PROTECT_FLAG_RDP_LEVEL1;
+
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RdpLevel {
+    Level0 = 0,
+    Level1 = 1,
+    Level2 = 2,
+}
+
+/// Option bytes as loaded at last reset
+#[derive(Clone, Copy)]
+pub struct OptionBytes {
+    /// FLASH_OPTR
+    pub optr: u32,
+    /// FLASH_WRP1AR
+    pub wrp1ar: u32,
+    /// FLASH_WRP1BR
+    pub wrp1br: u32,
+}
+
+impl OptionBytes {
+    #[cfg(target_arch = "arm")]
+    pub fn read() -> Self {
+        Self {
+            optr: FLASH.optr().read().0,
+            wrp1ar: FLASH.wrp1ar().read().0,
+            wrp1br: FLASH.wrp1br().read().0,
+        }
+    }
+
+    pub const fn rdp_level(&self) -> RdpLevel {
+        match self.optr as u8 {
+            RDP_LEVEL0 => RdpLevel::Level0,
+            RDP_LEVEL2 => RdpLevel::Level2,
+            _ => RdpLevel::Level1,
+        }
+    }
+
+    /// Every bootloader page is in WRP area A or B
+    pub fn is_bootloader_protected(&self) -> bool {
+        fn covers(wrp: u32) -> bool {
+            let area = (wrp & 0x3F) as u8..=((wrp >> 16) & 0x3F) as u8;
+
+            // area is empty when start is after end, i.e. disabled
+            area.contains(&BOOTLOADER_FIRST_PAGE) && area.contains(&BOOTLOADER_LAST_PAGE)
+        }
+
+        covers(self.wrp1ar) || covers(self.wrp1br)
+    }
+
+    /// `PROTECT_FLAG_*` in effect, level 2 counts as level 1
+    pub fn protect_flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.is_bootloader_protected() {
+            flags |= PROTECT_FLAG_WRP_BOOTLOADER;
+        }
+        if self.rdp_level() != RdpLevel::Level0 {
+            flags |= PROTECT_FLAG_RDP_LEVEL1;
+        }
+
+        flags
+    }
+}
+
+/// Program option bytes for `flags` (`PROTECT_FLAG_*`), other option bytes are kept.
+/// They're applied on [`reload`].
+#[cfg(target_arch = "arm")]
+pub fn program(flags: u8) -> Result<(), OtaError> {
+    if flags == 0 || (flags & !PROTECT_FLAGS) != 0 {
+        return Err(OtaError::InvalidArgument);
+    }
+
+    wait_busy();
+    FLASH.sr().write_value(Sr(SR_ERRORS));
+
+    unlock();
+
+    if (flags & PROTECT_FLAG_WRP_BOOTLOADER) != 0 {
+        let wrp1ar = (BOOTLOADER_LAST_PAGE as u32) << 16 | BOOTLOADER_FIRST_PAGE as u32;
+
+        // PAC has FLASH_WRP1AR read only, but it's the option byte register to program
+        unsafe { core::ptr::write_volatile(FLASH.wrp1ar().as_ptr() as *mut u32, wrp1ar) };
+    }
+    if (flags & PROTECT_FLAG_RDP_LEVEL1) != 0 && OptionBytes::read().rdp_level() == RdpLevel::Level0
+    {
+        FLASH.optr().modify(|w| w.set_rdp(Rdp(RDP_LEVEL1)));
+    }
+
+    FLASH.cr().modify(|w| w.set_optstrt(true));
+    wait_busy();
+
+    let errors = FLASH.sr().read().0 & SR_ERRORS;
+    // locking FLASH_CR locks option bytes too
+    FLASH.cr().modify(|w| w.set_lock(true));
+
+    if errors != 0 {
+        return Err(OtaError::FlashProg);
+    }
+
+    Ok(())
+}
+
+/// Load programmed option bytes, MCU resets right after
+#[cfg(target_arch = "arm")]
+pub fn reload() -> ! {
+    unlock();
+
+    FLASH.cr().modify(|w| w.set_obl_launch(true));
+
+    // in case reload didn't reset
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Unlock FLASH_CR then option bytes
+#[cfg(target_arch = "arm")]
+fn unlock() {
+    if FLASH.cr().read().lock() {
+        FLASH.keyr().write_value(FLASH_KEY1);
+        FLASH.keyr().write_value(FLASH_KEY2);
+    }
+    if FLASH.cr().read().optlock() {
+        FLASH.optkeyr().write_value(OPT_KEY1);
+        FLASH.optkeyr().write_value(OPT_KEY2);
+    }
+}
+
+#[cfg(target_arch = "arm")]
+fn wait_busy() {
+    while (FLASH.sr().read().0 & SR_BSY) != 0 {}
+}
@@ -4,17 +4,47 @@
  * SPDX-License-Identifier: MIT OR Apache-2.0
  */
 
-use core::borrow::BorrowMut;
-
-use chacha20::cipher::StreamCipher;
-use embassy_stm32::flash::WRITE_SIZE;
-
-use super::section_mark::{SectionMark, CHUNK_BIT_IDX, WRITE_CHUNK_SIZE};
-use crate::Board;
+use chacha20::cipher::{StreamCipher, StreamCipherSeek};
+use embassy_time::Instant;
+
+use super::app_image::{AppInfo, AppStatus};
+use super::boot_reason::BootReason;
+use super::capability;
+use super::frame::{
+    addressed_frame_len, v2_frame_len, Envelope, MAX_FRAME_OVERHEAD, PROTOCOL_VERSION_V1,
+    PROTOCOL_VERSION_V2, SOF_ADDRESSED_REQUEST, SOF_V2_REQUEST,
+};
+use super::frame_assembler::FrameFormat;
+use super::indicator::Indicator;
+use super::option_bytes::RdpLevel;
+use super::region::{self, PAGE_SIZE, WRITE_SIZE};
+use super::section_mark::{
+    SectionMark, BOOTLOADER_LENGTH, BOOTLOADER_ORIGIN, DEFAULT_CHUNK_BIT_IDX,
+    DEFAULT_WRITE_CHUNK_SIZE, FLASH_BASE, FLASH_SIZE, LEGACY_BITMAP_SIZE, MAX_CHUNK_BIT_IDX,
+    MAX_WRITE_CHUNK_SIZE, MIN_CHUNK_BIT_IDX, MIN_WRITE_CHUNK_SIZE, REMAIN_OFFSET, REMAIN_SIZE,
+};
+use super::write_window::{SequenceCheck, WriteWindow, MAX_WINDOW_SIZE};
+use crate::boards::const_str::{
+    self, FW_VER_LEN, GIT_DATETIME_LEN, GIT_HASH_LEN, HW_MODEL_LEN, PROJECT_NAME_LEN,
+};
+use crate::boards::{Board, Checksum, Device, OtaFlash};
 
 pub const EOF_SIGNATURE: u8 = 0xFF;
-pub const PROTOCOL_VERSION_BYTE: u8 = 0x01;
-pub const REASONABLE_TX_BUF: usize = (response_packet_max_size() + 15) / 8; // 8bytes padding
+/// `WriteChunk` flag, host wants response for this chunk (last one of window)
+pub const WRITE_CHUNK_FLAG_ACK: u8 = 0x01;
+/// `WriteChunk` flag, chunk is written by its offset without touching sequence window.
+/// Broadcast chunks are always unsequenced, host repairs missed ones with this flag.
+pub const WRITE_CHUNK_FLAG_UNSEQUENCED: u8 = 0x02;
+/// `StartUpdate` flag, cipher runs with `nonce` of the request instead of device's own
+pub const START_UPDATE_FLAG_HOST_NONCE: u8 = 0x01;
+/// `StartUpdate` flag, request carries `image_length` and application pages up to it
+/// are erased, so host may omit chunks that are all `0xFF`
+pub const START_UPDATE_FLAG_IMAGE_LENGTH: u8 = 0x02;
+pub const SUPPORTED_BAUDRATES: [u32; 5] = [115200, 230400, 460800, 921600, 1000000];
+pub const REASONABLE_TX_BUF: usize = (response_packet_max_size() + MAX_FRAME_OVERHEAD + 7) / 8 * 8; // 8bytes padding
+/// Largest request frame, v2 `WriteChunk` carrying the biggest negotiable chunk
+pub const REASONABLE_RX_BUF: usize =
+    write_chunk_request_size(MAX_WRITE_CHUNK_SIZE) + MAX_FRAME_OVERHEAD;
 
 #[macro_export]
 macro_rules! on_tx_buffer {
@@ -33,9 +63,19 @@ macro_rules! on_tx_buffer {
 pub enum Command {
     Handshake = 0x01,
     DeviceInfo = 0x02,
+    MemoryMap = 0x03,
+    BootloaderInfo = 0x04,
+    AppInfo = 0x05,
+    Diagnostics = 0x06,
+    OptionBytes = 0x07,
+    SetBaudRate = 0x10,
     StartUpdate = 0x30,
     WriteChunk = 0x40,
+    SetProtection = 0x50,
+    /// Response only, rejected request
+    Nack = 0x7F,
     UpdateStatus = 0xE0,
+    Finalize = 0xE1,
     Reset = 0xF0,
     JumpToApplication = 0xF1,
 }
@@ -47,40 +87,123 @@ impl TryFrom<u8> for Command {
         match value {
             const { Self::Handshake as u8 } => Ok(Self::Handshake),
             const { Self::DeviceInfo as u8 } => Ok(Self::DeviceInfo),
+            const { Self::MemoryMap as u8 } => Ok(Self::MemoryMap),
+            const { Self::BootloaderInfo as u8 } => Ok(Self::BootloaderInfo),
+            const { Self::AppInfo as u8 } => Ok(Self::AppInfo),
+            const { Self::Diagnostics as u8 } => Ok(Self::Diagnostics),
+            const { Self::OptionBytes as u8 } => Ok(Self::OptionBytes),
+            const { Self::SetBaudRate as u8 } => Ok(Self::SetBaudRate),
             const { Self::StartUpdate as u8 } => Ok(Self::StartUpdate),
             const { Self::WriteChunk as u8 } => Ok(Self::WriteChunk),
+            const { Self::SetProtection as u8 } => Ok(Self::SetProtection),
             const { Self::UpdateStatus as u8 } => Ok(Self::UpdateStatus),
+            const { Self::Finalize as u8 } => Ok(Self::Finalize),
             const { Self::Reset as u8 } => Ok(Self::Reset),
+            const { Self::JumpToApplication as u8 } => Ok(Self::JumpToApplication),
             _ => Err(OtaError::UnknownCommand),
         }
     }
 }
 
 pub enum RequestForm<'a> {
-    Handshake,
+    /// `None` for legacy handshake without version negotiation
+    Handshake(Option<&'a HandshakeRequestForm>),
     DeviceInfo,
-    StartUpdate,
+    MemoryMap,
+    BootloaderInfo,
+    AppInfo,
+    Diagnostics,
+    OptionBytes,
+    SetBaudRate(&'a SetBaudRateRequestForm),
+    /// `StartUpdate` of old host tools, transfer keeps legacy layouts
+    LegacyStartUpdate,
+    /// `host_nonce` is given for broadcast, every device decrypts the same stream
+    StartUpdate {
+        payload_exponent: u8,
+        host_nonce: Option<&'a [u8; 12]>,
+        /// given for sparse transfer
+        image_length: Option<u32>,
+    },
     WriteChunk(&'a WriteChunkRequestForm),
+    LegacyWriteChunk(&'a LegacyWriteChunkRequestForm),
+    SetProtection(&'a SetProtectionRequestForm),
     UpdateStatus,
+    Finalize(&'a FinalizeRequestForm),
     Reset,
     JumpToApplication,
 }
 
-impl RequestForm<'_> {
-    unsafe fn transmute(cmd: Command, arr: &'_ [u8]) -> Self {
-        match cmd {
-            Command::Handshake => Self::Handshake,
+impl<'a> RequestForm<'a> {
+    /// `Err(UnknownCommand)` for response only command
+    unsafe fn transmute(cmd: Command, arr: &'a [u8]) -> Result<Self, OtaError> {
+        let ret = match cmd {
+            Command::Handshake => {
+                if arr.len() == core::mem::size_of::<HandshakeRequestForm>() {
+                    Self::Handshake(Some(&*(arr.as_ptr() as *const _)))
+                } else {
+                    Self::Handshake(None)
+                }
+            }
             Command::DeviceInfo => Self::DeviceInfo,
-            Command::StartUpdate => Self::StartUpdate,
-            Command::WriteChunk => Self::WriteChunk(&*(arr.as_ptr() as *const _)),
+            Command::MemoryMap => Self::MemoryMap,
+            Command::BootloaderInfo => Self::BootloaderInfo,
+            Command::AppInfo => Self::AppInfo,
+            Command::Diagnostics => Self::Diagnostics,
+            Command::OptionBytes => Self::OptionBytes,
+            Command::SetBaudRate => Self::SetBaudRate(&*(arr.as_ptr() as *const _)),
+            Command::StartUpdate => {
+                if arr.len() == core::mem::size_of::<LegacyStartUpdateRequestForm>() {
+                    Self::LegacyStartUpdate
+                } else if arr.len() == core::mem::size_of::<StartUpdateImageRequestForm>() {
+                    let form = &*(arr.as_ptr() as *const StartUpdateImageRequestForm);
+
+                    Self::StartUpdate {
+                        payload_exponent: form.payload_exponent,
+                        host_nonce: ((form.flags & START_UPDATE_FLAG_HOST_NONCE) != 0)
+                            .then_some(&form.nonce),
+                        image_length: Some(u32::from_le_bytes(form.image_length)),
+                    }
+                } else if arr.len() == core::mem::size_of::<StartUpdateNonceRequestForm>() {
+                    let form = &*(arr.as_ptr() as *const StartUpdateNonceRequestForm);
+
+                    Self::StartUpdate {
+                        payload_exponent: form.payload_exponent,
+                        host_nonce: ((form.flags & START_UPDATE_FLAG_HOST_NONCE) != 0)
+                            .then_some(&form.nonce),
+                        image_length: None,
+                    }
+                } else {
+                    let form = &*(arr.as_ptr() as *const StartUpdateRequestForm);
+
+                    Self::StartUpdate {
+                        payload_exponent: form.payload_exponent,
+                        host_nonce: None,
+                        image_length: None,
+                    }
+                }
+            }
+            // sequenced chunk is never as long as legacy one, whose payload length is odd
+            Command::WriteChunk
+                if arr.len() == core::mem::size_of::<LegacyWriteChunkRequestForm>() =>
+            {
+                Self::LegacyWriteChunk(&*(arr.as_ptr() as *const _))
+            }
+            Command::WriteChunk => Self::WriteChunk(WriteChunkRequestForm::from_raw(arr)),
+            Command::SetProtection => Self::SetProtection(&*(arr.as_ptr() as *const _)),
+            // response only, host never sends it
+            Command::Nack => return Err(OtaError::UnknownCommand),
             Command::UpdateStatus => Self::UpdateStatus,
+            Command::Finalize => Self::Finalize(&*(arr.as_ptr() as *const _)),
             Command::Reset => Self::Reset,
             Command::JumpToApplication => Self::JumpToApplication,
-        }
+        };
+
+        Ok(ret)
     }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(test, derive(Debug))]
 pub enum OtaError {
     Nothing = 0,
     ChecksumError = 0x80,
@@ -88,6 +211,11 @@ pub enum OtaError {
     OutOfRange = 0x82,
     MissingEof = 0x83,
     MissingSof = 0x84,
+    InvalidArgument = 0x85,
+    SequenceGap = 0x86,
+    UnsupportedVersion = 0x87,
+    /// Offset is outside of application region
+    OutOfRegion = 0x88,
     FlashProg = 0x90,
     FlashSize = 0x91,
     FlashMiss = 0x92,
@@ -95,31 +223,30 @@ pub enum OtaError {
     FlashProtected = 0x94,
     FlashUnaligned = 0x95,
     FlashParallelism = 0x96,
+    /// Programmed data read back differs, chunk can't be written again until erase
+    FlashVerify = 0x97,
     #[allow(unused)]
     UnknownError = 0xFF,
 }
 
-impl From<embassy_stm32::flash::Error> for OtaError {
-    fn from(value: embassy_stm32::flash::Error) -> Self {
-        match value {
-            embassy_stm32::flash::Error::Prog => Self::FlashProg,
-            embassy_stm32::flash::Error::Size => Self::FlashSize,
-            embassy_stm32::flash::Error::Miss => Self::FlashMiss,
-            embassy_stm32::flash::Error::Seq => Self::FlashSeq,
-            embassy_stm32::flash::Error::Protected => Self::FlashProtected,
-            embassy_stm32::flash::Error::Unaligned => Self::FlashUnaligned,
-            embassy_stm32::flash::Error::Parallelism => Self::FlashParallelism,
-        }
-    }
-}
-
-const fn request_packet_size(command: Command) -> usize {
+/// `WriteChunk` is the only variable-length request, its payload is the chunk size
+/// negotiated on `StartUpdate`
+const fn request_packet_size(command: Command, chunk_size: usize) -> usize {
     match command {
-        Command::Handshake => core::mem::size_of::<HandshakeForm>(),
+        Command::Handshake => core::mem::size_of::<HandshakeRequestForm>(),
         Command::DeviceInfo => core::mem::size_of::<DeviceInfoRequestForm>(),
-        Command::StartUpdate => core::mem::size_of::<StartUpdateRequestForm>(),
-        Command::WriteChunk => core::mem::size_of::<WriteChunkRequestForm>(),
+        Command::MemoryMap => core::mem::size_of::<MemoryMapRequestForm>(),
+        Command::BootloaderInfo => core::mem::size_of::<BootloaderInfoRequestForm>(),
+        Command::AppInfo => core::mem::size_of::<AppInfoRequestForm>(),
+        Command::Diagnostics => core::mem::size_of::<DiagnosticsRequestForm>(),
+        Command::OptionBytes => core::mem::size_of::<OptionBytesRequestForm>(),
+        Command::SetBaudRate => core::mem::size_of::<SetBaudRateRequestForm>(),
+        Command::StartUpdate => core::mem::size_of::<StartUpdateImageRequestForm>(),
+        Command::WriteChunk => write_chunk_request_size(chunk_size),
+        Command::SetProtection => core::mem::size_of::<SetProtectionRequestForm>(),
+        Command::Nack => core::mem::size_of::<NackResponseForm>(),
         Command::UpdateStatus => core::mem::size_of::<UpdateStatusRequestForm>(),
+        Command::Finalize => core::mem::size_of::<FinalizeRequestForm>(),
         Command::Reset => core::mem::size_of::<ResetForm>(),
         Command::JumpToApplication => core::mem::size_of::<JumpToApplicationForm>(),
     }
@@ -128,11 +255,20 @@ const fn request_packet_size(command: Command) -> usize {
 #[allow(unused)]
 const fn response_packet_size(command: Command) -> usize {
     match command {
-        Command::Handshake => core::mem::size_of::<HandshakeForm>(),
-        Command::DeviceInfo => core::mem::size_of::<DeviceInfoResponseForm>(),
+        Command::Handshake => core::mem::size_of::<HandshakeResponseForm>(),
+        Command::DeviceInfo => core::mem::size_of::<DeviceInfoV2ResponseForm>(),
+        Command::MemoryMap => core::mem::size_of::<MemoryMapResponseForm>(),
+        Command::BootloaderInfo => core::mem::size_of::<BootloaderInfoResponseForm>(),
+        Command::AppInfo => core::mem::size_of::<AppInfoResponseForm>(),
+        Command::Diagnostics => core::mem::size_of::<DiagnosticsResponseForm>(),
+        Command::OptionBytes => core::mem::size_of::<OptionBytesResponseForm>(),
+        Command::SetBaudRate => core::mem::size_of::<SetBaudRateResponseForm>(),
         Command::StartUpdate => core::mem::size_of::<StartUpdateResponseForm>(),
         Command::WriteChunk => core::mem::size_of::<WriteChunkResponseForm>(),
+        Command::SetProtection => core::mem::size_of::<SetProtectionResponseForm>(),
+        Command::Nack => core::mem::size_of::<NackResponseForm>(),
         Command::UpdateStatus => core::mem::size_of::<UpdateStatusResponseForm>(),
+        Command::Finalize => core::mem::size_of::<FinalizeResponseForm>(),
         Command::Reset => core::mem::size_of::<ResetForm>(),
         Command::JumpToApplication => core::mem::size_of::<JumpToApplicationForm>(),
     }
@@ -148,36 +284,135 @@ const fn response_packet_max_size() -> usize {
     }
     let mut ret = response_packet_size(Command::Handshake);
     ret = max(ret, response_packet_size(Command::DeviceInfo));
+    ret = max(ret, response_packet_size(Command::MemoryMap));
+    ret = max(ret, response_packet_size(Command::BootloaderInfo));
+    ret = max(ret, response_packet_size(Command::AppInfo));
+    ret = max(ret, response_packet_size(Command::Diagnostics));
+    ret = max(ret, response_packet_size(Command::OptionBytes));
+    ret = max(ret, response_packet_size(Command::SetBaudRate));
     ret = max(ret, response_packet_size(Command::StartUpdate));
     ret = max(ret, response_packet_size(Command::WriteChunk));
+    ret = max(ret, response_packet_size(Command::SetProtection));
+    ret = max(ret, response_packet_size(Command::Nack));
     ret = max(ret, response_packet_size(Command::UpdateStatus));
+    ret = max(ret, response_packet_size(Command::Finalize));
     max(ret, response_packet_size(Command::Reset))
 }
 
 #[allow(unused)]
-const fn packet_size(sof: Sof, command: Command) -> usize {
+const fn packet_size(sof: Sof, command: Command, chunk_size: usize) -> usize {
     match sof {
-        Sof::Request => request_packet_size(command),
+        Sof::Request => request_packet_size(command, chunk_size),
         Sof::Response => response_packet_size(command),
     }
 }
 
-pub(crate) fn test_packet<'a>(packet: &[u8]) -> Result<RequestForm<'a>, OtaError> {
-    if packet[0] != Sof::Request as u8 {
-        return Err(OtaError::MissingSof);
+/// sof + command + checksum + sequence + flags + offset + payload + eof
+pub const fn write_chunk_request_size(chunk_size: usize) -> usize {
+    1 + 1 + 2 + 2 + 1 + 4 + chunk_size + 1
+}
+
+/// Length of request frame at the beginning of `packet`,
+/// `OutOfRange` when the frame is not fully received yet
+pub(crate) fn request_frame_len(packet: &[u8], format: &RequestFrame) -> Result<usize, OtaError> {
+    match packet.first() {
+        None => return Err(OtaError::OutOfRange),
+        Some(&sof) if sof != Sof::Request as u8 => return Err(OtaError::MissingSof),
+        Some(_) => {}
     }
 
-    let cmd = Command::try_from(packet[1])?;
+    let cmd = Command::try_from(*packet.get(1).ok_or(OtaError::OutOfRange)?)?;
 
-    let estimated_packet_size = request_packet_size(cmd);
+    let estimated_packet_size = match cmd {
+        // legacy handshake has EOF right after command, host versions bitmask is never 0xFF
+        Command::Handshake if packet.get(2) == Some(&EOF_SIGNATURE) => {
+            core::mem::size_of::<HandshakeForm>()
+        }
+        // legacy start update has EOF right after command, payload exponent is never 0xFF
+        Command::StartUpdate if packet.get(2) == Some(&EOF_SIGNATURE) => {
+            core::mem::size_of::<LegacyStartUpdateRequestForm>()
+        }
+        // short start update has EOF right after exponent, flags are never 0xFF
+        Command::StartUpdate if packet.get(3) == Some(&EOF_SIGNATURE) => {
+            core::mem::size_of::<StartUpdateRequestForm>()
+        }
+        Command::StartUpdate
+            if packet
+                .get(3)
+                .is_some_and(|flags| (flags & START_UPDATE_FLAG_IMAGE_LENGTH) == 0) =>
+        {
+            core::mem::size_of::<StartUpdateNonceRequestForm>()
+        }
+        Command::WriteChunk if format.legacy_transfer => {
+            core::mem::size_of::<LegacyWriteChunkRequestForm>()
+        }
+        _ => request_packet_size(cmd, format.chunk_size),
+    };
 
     if packet.len() < estimated_packet_size {
-        return Err(OtaError::OutOfRange);
+        Err(OtaError::OutOfRange)
     } else if packet[estimated_packet_size - 1] != EOF_SIGNATURE {
-        return Err(OtaError::MissingEof);
+        Err(OtaError::MissingEof)
+    } else {
+        Ok(estimated_packet_size)
+    }
+}
+
+pub(crate) fn test_packet<'a>(
+    packet: &'a [u8],
+    format: &RequestFrame,
+) -> Result<RequestForm<'a>, OtaError> {
+    let len = request_frame_len(packet, format)?;
+    let cmd = Command::try_from(packet[1])?;
+
+    unsafe { RequestForm::transmute(cmd, &packet[..len]) }
+}
+
+/// [`FrameFormat`] of host requests for [`super::frame_assembler::FrameAssembler`],
+/// v1 and v2 frames are recognized with or without address prefix
+pub struct RequestFrame {
+    /// negotiated `WriteChunk` payload size
+    pub chunk_size: usize,
+    /// `WriteChunk` comes in legacy layout
+    pub legacy_transfer: bool,
+}
+
+impl RequestFrame {
+    fn unaddressed_frame_len(&self, head: &[u8]) -> Result<usize, OtaError> {
+        match head.first().copied() {
+            Some(SOF_V2_REQUEST) => v2_frame_len(head),
+            _ => request_frame_len(head, self),
+        }
+    }
+}
+
+impl FrameFormat for RequestFrame {
+    #[inline]
+    fn is_start(&self, byte: u8) -> bool {
+        byte == Sof::Request as u8 || byte == SOF_V2_REQUEST || byte == SOF_ADDRESSED_REQUEST
     }
 
-    Ok(unsafe { RequestForm::transmute(cmd, packet) })
+    /// Only v1 frames end with a fixed byte, v2 ones end with CRC
+    #[inline]
+    fn is_end(&self, byte: u8) -> bool {
+        byte == EOF_SIGNATURE
+    }
+
+    fn frame_len(&self, head: &[u8]) -> Result<usize, OtaError> {
+        match head.first().copied() {
+            Some(SOF_ADDRESSED_REQUEST) => {
+                addressed_frame_len(head, |inner| self.unaddressed_frame_len(inner))
+            }
+            _ => self.unaddressed_frame_len(head),
+        }
+    }
+
+    fn command_of(&self, head: &[u8]) -> Option<u8> {
+        match head {
+            [SOF_ADDRESSED_REQUEST, _, inner @ ..] => Envelope::peek(inner).1,
+            _ => Envelope::peek(head).1,
+        }
+    }
 }
 
 #[repr(u8)]
@@ -189,6 +424,7 @@ pub enum Sof {
     Response = 0xBB,
 }
 
+/// Legacy handshake without payload, still answered for old host tools
 #[repr(C)]
 pub struct HandshakeForm {
     pub sof: Sof,
@@ -215,6 +451,69 @@ impl HandshakeForm {
     }
 }
 
+#[repr(C)]
+pub struct HandshakeRequestForm {
+    pub sof: Sof,
+    pub command: Command,
+    /// protocol versions host can speak, bit `n` stands for version `n`
+    pub host_versions: u8,
+    pub eof: u8,
+}
+
+impl HandshakeRequestForm {
+    #[allow(unused)]
+    pub const fn new(host_versions: u8) -> Self {
+        Self {
+            sof: Sof::Request,
+            command: Command::Handshake,
+            host_versions,
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+#[repr(C)]
+pub struct HandshakeResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub checksum: [u8; 2],
+    /// protocol versions bootloader can speak, bit `n` stands for version `n`
+    pub supported_versions: u8,
+    /// highest common version, 0 when there's nothing in common
+    pub protocol_version: u8,
+    pub capabilities: [u8; 4], // little endian
+    pub eof: u8,
+}
+
+impl HandshakeResponseForm {
+    pub fn checksum_source(&self) -> &[u8] {
+        unsafe {
+            let start_ptr = &self.supported_versions as *const u8;
+            let end_ptr = &self.eof as *const u8;
+
+            core::slice::from_raw_parts(start_ptr, end_ptr as usize - start_ptr as usize)
+        }
+    }
+
+    pub fn new<D: Device>(board: &mut Board<D>, request: &HandshakeRequestForm) -> Self {
+        let mut ret = Self {
+            sof: Sof::Response,
+            command: Command::Handshake,
+            checksum: [0; 2],
+            supported_versions: capability::SUPPORTED_VERSIONS,
+            protocol_version: capability::negotiate_version(request.host_versions).unwrap_or(0),
+            capabilities: capability::CAPABILITIES.to_le_bytes(),
+            eof: EOF_SIGNATURE,
+        };
+
+        let crc = board.hardware.crc();
+        crc.reset();
+        ret.checksum = (crc.feed_bytes(ret.checksum_source()) as u16).to_le_bytes();
+
+        ret
+    }
+}
+
 #[repr(C)]
 pub struct DeviceInfoRequestForm {
     pub sof: Sof,
@@ -254,19 +553,62 @@ impl DeviceInfoResponseForm {
         }
     }
 
-    pub fn new(board: &mut Board) -> Self {
-        let crc = board.hardware.crc.borrow_mut();
+    pub fn new<D: Device>(board: &mut Board<D>) -> Self {
+        let mut ret = Self {
+            sof: Sof::Response,
+            command: Command::DeviceInfo,
+            checksum: [0; 2],
+            protocol_version: PROTOCOL_VERSION_V1,
+            payload_exponent: DEFAULT_CHUNK_BIT_IDX as u8,
+            serial_number: board.hardware.serial_number(),
+            eof: EOF_SIGNATURE,
+        };
+
+        let crc = board.hardware.crc();
+        crc.reset();
+        ret.checksum = (crc.feed_bytes(ret.checksum_source()) as u16).to_le_bytes();
+
+        ret
+    }
+}
+
+/// `DeviceInfo` answered in v2 frame, v1 one stays as old host tools know it.
+/// `protection` follows `PROTECT_FLAG_*` in effect.
+#[repr(C)]
+pub struct DeviceInfoV2ResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub checksum: [u8; 2],
+    pub protocol_version: u8,
+    pub payload_exponent: u8,
+    pub serial_number: [u8; 12],
+    pub protection: u8,
+    pub eof: u8,
+}
+
+impl DeviceInfoV2ResponseForm {
+    pub fn checksum_source(&self) -> &[u8] {
+        unsafe {
+            let start_ptr = &self.protocol_version as *const u8;
+            let end_ptr = &self.eof as *const u8;
+
+            core::slice::from_raw_parts(start_ptr, end_ptr as usize - start_ptr as usize)
+        }
+    }
 
+    pub fn new<D: Device>(board: &mut Board<D>) -> Self {
         let mut ret = Self {
             sof: Sof::Response,
             command: Command::DeviceInfo,
             checksum: [0; 2],
-            protocol_version: PROTOCOL_VERSION_BYTE,
-            payload_exponent: CHUNK_BIT_IDX as u8,
-            serial_number: Board::get_serial_number(),
+            protocol_version: PROTOCOL_VERSION_V2,
+            payload_exponent: DEFAULT_CHUNK_BIT_IDX as u8,
+            serial_number: board.hardware.serial_number(),
+            protection: board.hardware.option_bytes().protect_flags(),
             eof: EOF_SIGNATURE,
         };
 
+        let crc = board.hardware.crc();
         crc.reset();
         ret.checksum = (crc.feed_bytes(ret.checksum_source()) as u16).to_le_bytes();
 
@@ -275,192 +617,1240 @@ impl DeviceInfoResponseForm {
 }
 
 #[repr(C)]
-pub struct StartUpdateRequestForm {
+pub struct MemoryMapRequestForm {
     pub sof: Sof,
     pub command: Command,
     pub eof: u8,
 }
 
-impl StartUpdateRequestForm {
+impl MemoryMapRequestForm {
     #[allow(unused)]
     pub const fn new() -> Self {
         Self {
             sof: Sof::Request,
-            command: Command::StartUpdate,
+            command: Command::MemoryMap,
             eof: EOF_SIGNATURE,
         }
     }
 }
 
+/// Flash layout and buffer limits, so host tools don't need to hard-code them per MCU.
+/// Every multi-byte field is little endian.
 #[repr(C)]
-pub struct StartUpdateResponseForm {
+pub struct MemoryMapResponseForm {
     pub sof: Sof,
     pub command: Command,
     pub checksum: [u8; 2],
-    pub nonce: [u8; 12],
+    pub flash_base: [u8; 4],
+    pub flash_size: [u8; 4],
+    pub bootloader_origin: [u8; 4],
+    pub bootloader_length: [u8; 4],
+    /// offset from `flash_base`, same unit as `WriteChunk` offset
+    pub app_offset: [u8; 4],
+    pub app_size: [u8; 4],
+    pub page_size: [u8; 4],
+    pub write_size: [u8; 2],
+    pub min_payload_exponent: u8,
+    pub max_payload_exponent: u8,
+    pub max_window_size: u8,
+    pub max_request_size: [u8; 2],
+    pub max_response_size: [u8; 2],
+    pub uart_rx_buf_size: [u8; 2],
     pub eof: u8,
 }
 
-impl StartUpdateResponseForm {
-    pub fn new(board: &mut Board) -> Self {
-        let crc = board.hardware.crc.borrow_mut();
+impl MemoryMapResponseForm {
+    pub fn checksum_source(&self) -> &[u8] {
+        unsafe {
+            let start_ptr = &self.flash_base as *const u8;
+            let end_ptr = &self.eof as *const u8;
+
+            core::slice::from_raw_parts(start_ptr, end_ptr as usize - start_ptr as usize)
+        }
+    }
 
+    pub fn new<D: Device>(board: &mut Board<D>) -> Self {
         let mut ret = Self {
             sof: Sof::Response,
-            command: Command::StartUpdate,
+            command: Command::MemoryMap,
             checksum: [0; 2],
-            nonce: Board::get_nonce(),
+            flash_base: (FLASH_BASE as u32).to_le_bytes(),
+            flash_size: (FLASH_SIZE as u32).to_le_bytes(),
+            bootloader_origin: (BOOTLOADER_ORIGIN as u32).to_le_bytes(),
+            bootloader_length: (BOOTLOADER_LENGTH as u32).to_le_bytes(),
+            app_offset: (REMAIN_OFFSET as u32).to_le_bytes(),
+            app_size: (REMAIN_SIZE as u32).to_le_bytes(),
+            page_size: (PAGE_SIZE as u32).to_le_bytes(),
+            write_size: (WRITE_SIZE as u16).to_le_bytes(),
+            min_payload_exponent: MIN_CHUNK_BIT_IDX as u8,
+            max_payload_exponent: MAX_CHUNK_BIT_IDX as u8,
+            max_window_size: MAX_WINDOW_SIZE as u8,
+            max_request_size: (REASONABLE_RX_BUF as u16).to_le_bytes(),
+            max_response_size: (REASONABLE_TX_BUF as u16).to_le_bytes(),
+            uart_rx_buf_size: (D::UART_RX_BUF_SIZE as u16).to_le_bytes(),
             eof: EOF_SIGNATURE,
         };
 
+        let crc = board.hardware.crc();
         crc.reset();
-        let checksum = crc.feed_bytes(&ret.nonce);
-
-        ret.checksum = (checksum as u16).to_le_bytes();
+        ret.checksum = (crc.feed_bytes(ret.checksum_source()) as u16).to_le_bytes();
 
         ret
     }
 }
 
 #[repr(C)]
-pub struct WriteChunkRequestForm {
+pub struct BootloaderInfoRequestForm {
     pub sof: Sof,
     pub command: Command,
-    pub checksum: [u8; 2], // little endian
-    pub offset: [u8; 4],   // little endian
-    pub payload: [u8; WRITE_CHUNK_SIZE],
     pub eof: u8,
 }
 
-impl WriteChunkRequestForm {
+impl BootloaderInfoRequestForm {
+    #[allow(unused)]
+    pub const fn new() -> Self {
+        Self {
+            sof: Sof::Request,
+            command: Command::BootloaderInfo,
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+/// Build identity of running bootloader, strings are ASCII padded with zero
+#[repr(C)]
+pub struct BootloaderInfoResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub checksum: [u8; 2],
+    pub project_name: [u8; PROJECT_NAME_LEN],
+    pub version: [u8; FW_VER_LEN],
+    pub commit_short: [u8; GIT_HASH_LEN],
+    pub commit_datetime: [u8; GIT_DATETIME_LEN],
+    pub hw_model: [u8; HW_MODEL_LEN],
+    pub eof: u8,
+}
+
+impl BootloaderInfoResponseForm {
     pub fn checksum_source(&self) -> &[u8] {
         unsafe {
-            let start_ptr = &self.offset as *const u8;
+            let start_ptr = &self.project_name as *const u8;
             let end_ptr = &self.eof as *const u8;
 
             core::slice::from_raw_parts(start_ptr, end_ptr as usize - start_ptr as usize)
         }
     }
 
-    // #[cfg(any(not(feature = "no_std"), feature = "std", test))]
-    #[allow(unused)]
-    pub fn new_std(offset: u32, bytes: &[u8; WRITE_CHUNK_SIZE]) -> Result<Self, OtaError> {
-        // if offset + bytes.len() as u32 > size {
-        //     return Err(Error::Size);
-        // }
-        if offset % WRITE_SIZE as u32 != 0 || bytes.len() % WRITE_SIZE != 0 {
-            return Err(OtaError::FlashUnaligned);
-        }
+    pub fn new<D: Device>(board: &mut Board<D>) -> Self {
         let mut ret = Self {
-            sof: Sof::Request,
-            command: Command::WriteChunk,
+            sof: Sof::Response,
+            command: Command::BootloaderInfo,
             checksum: [0; 2],
-            offset: offset.to_le_bytes(),
-            payload: *bytes,
+            project_name: const_str::PROJECT_NAME_ARRAY,
+            version: const_str::VERSION_STR,
+            commit_short: const_str::COMMIT_SHORT,
+            commit_datetime: const_str::GIT_COMMIT_DATETIME_ARRAY,
+            hw_model: const_str::HW_MODEL_ARRAY,
             eof: EOF_SIGNATURE,
         };
 
-        ret.checksum = (crate::types::std_crc::std_crc(ret.checksum_source()) as u16).to_le_bytes();
+        let crc = board.hardware.crc();
+        crc.reset();
+        ret.checksum = (crc.feed_bytes(ret.checksum_source()) as u16).to_le_bytes();
 
-        Ok(ret)
+        ret
     }
+}
 
-    pub(crate) fn try_flash(&self, board: &mut Board) -> Result<(), OtaError> {
-        let flash = board.hardware.flash.borrow_mut();
-        let crc = board.hardware.crc.borrow_mut();
-        // let cipher = unsafe { &mut *board.shared_resource.cipher.get() };
-        // let mut cipher = board.shared_resource;
-
-        let mut data = self.payload;
-
-        crc.reset();
-        let actual = crc.feed_bytes(self.checksum_source()) as u16;
+#[repr(C)]
+pub struct AppInfoRequestForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub eof: u8,
+}
 
-        let expected = u16::from_le_bytes(self.checksum);
-        if actual != expected {
-            return Err(OtaError::ChecksumError);
+impl AppInfoRequestForm {
+    #[allow(unused)]
+    pub const fn new() -> Self {
+        Self {
+            sof: Sof::Request,
+            command: Command::AppInfo,
+            eof: EOF_SIGNATURE,
         }
-
-        let address = u32::from_le_bytes(self.offset);
-
-        board.shared_resource.cipher.apply_keystream(&mut data); // decrypt
-
-        flash.bank1_region.blocking_write(address, &data)?;
-
-        board.shared_resource.section_mark.mark_offset(address);
-
-        Ok(())
     }
 }
 
+/// Installed application, header fields are zero when there's no header
 #[repr(C)]
-pub struct WriteChunkResponseForm {
+pub struct AppInfoResponseForm {
     pub sof: Sof,
     pub command: Command,
-    pub result: OtaError,
+    pub checksum: [u8; 2],
+    pub status: AppStatus,
+    pub confirmed: u8,
+    pub header_version: u8,
+    pub fw_version: [u8; 8],
+    pub image_length: [u8; 4], // little endian
+    pub image_crc: [u8; 4],    // little endian
     pub eof: u8,
 }
 
-impl WriteChunkResponseForm {
-    pub fn new(result: Result<(), OtaError>) -> Self {
-        Self {
+impl AppInfoResponseForm {
+    pub fn checksum_source(&self) -> &[u8] {
+        unsafe {
+            let start_ptr = &self.status as *const AppStatus as *const u8;
+            let end_ptr = &self.eof as *const u8;
+
+            core::slice::from_raw_parts(start_ptr, end_ptr as usize - start_ptr as usize)
+        }
+    }
+
+    pub fn new<D: Device>(board: &mut Board<D>) -> Self {
+        let info = AppInfo::inspect(&mut board.hardware);
+
+        let mut ret = Self {
             sof: Sof::Response,
-            command: Command::WriteChunk,
-            result: result.map_or_else(|e| e, |_| OtaError::Nothing),
+            command: Command::AppInfo,
+            checksum: [0; 2],
+            status: info.status,
+            confirmed: info.confirmed as u8,
+            header_version: 0,
+            fw_version: [0; 8],
+            image_length: [0; 4],
+            image_crc: [0; 4],
             eof: EOF_SIGNATURE,
+        };
+
+        if let Some(header) = info.header {
+            ret.header_version = header.header_version;
+            ret.fw_version = header.fw_version;
+            ret.image_length = header.image_length;
+            ret.image_crc = header.image_crc;
         }
+
+        let crc = board.hardware.crc();
+        crc.reset();
+        ret.checksum = (crc.feed_bytes(ret.checksum_source()) as u16).to_le_bytes();
+
+        ret
     }
 }
 
 #[repr(C)]
-pub struct UpdateStatusRequestForm {
+pub struct DiagnosticsRequestForm {
     pub sof: Sof,
     pub command: Command,
     pub eof: u8,
 }
 
-impl UpdateStatusRequestForm {
+impl DiagnosticsRequestForm {
     #[allow(unused)]
     pub const fn new() -> Self {
         Self {
-            sof: Sof::Response,
-            command: Command::UpdateStatus,
+            sof: Sof::Request,
+            command: Command::Diagnostics,
             eof: EOF_SIGNATURE,
         }
     }
 }
 
-// acutal MCU will use above form
+/// Why bootloader is resident, `reset_flags` follows `ResetFlags` bits
 #[repr(C)]
-pub struct UpdateStatusResponseForm {
+pub struct DiagnosticsResponseForm {
     pub sof: Sof,
     pub command: Command,
     pub checksum: [u8; 2],
-    pub chunk_mark: SectionMark,
+    pub boot_reason: BootReason,
+    pub reset_flags: u8,
+    pub app_status: AppStatus,
+    pub mailbox_command: u8,
+    pub boot_param: [u8; 4], // little endian
+    pub uptime_ms: [u8; 4],  // little endian
     pub eof: u8,
 }
 
-impl UpdateStatusResponseForm {
-    pub fn new(board: &mut Board) -> Self {
-        let crc = board.hardware.crc.borrow_mut();
+impl DiagnosticsResponseForm {
+    pub fn checksum_source(&self) -> &[u8] {
+        unsafe {
+            let start_ptr = &self.boot_reason as *const BootReason as *const u8;
+            let end_ptr = &self.eof as *const u8;
 
-        let mut ret: Self = Self {
+            core::slice::from_raw_parts(start_ptr, end_ptr as usize - start_ptr as usize)
+        }
+    }
+
+    pub fn new<D: Device>(board: &mut Board<D>) -> Self {
+        let diagnostics = board.shared_resource.boot_diagnostics;
+
+        let mut ret = Self {
             sof: Sof::Response,
-            command: Command::UpdateStatus,
+            command: Command::Diagnostics,
             checksum: [0; 2],
-            chunk_mark: board.shared_resource.section_mark.clone(),
+            boot_reason: diagnostics.reason,
+            reset_flags: diagnostics.reset_flags.0,
+            app_status: AppInfo::inspect(&mut board.hardware).status,
+            mailbox_command: diagnostics.mailbox_command,
+            boot_param: diagnostics.boot_param.to_le_bytes(),
+            uptime_ms: (Instant::now().as_millis() as u32).to_le_bytes(),
             eof: EOF_SIGNATURE,
         };
 
+        let crc = board.hardware.crc();
         crc.reset();
-        let checksum = crc.feed_bytes(&ret.chunk_mark.bitmap);
-
-        ret.checksum = (checksum as u16).to_le_bytes();
+        ret.checksum = (crc.feed_bytes(ret.checksum_source()) as u16).to_le_bytes();
 
         ret
     }
 }
 
+#[repr(C)]
+pub struct OptionBytesRequestForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub eof: u8,
+}
+
+impl OptionBytesRequestForm {
+    #[allow(unused)]
+    pub const fn new() -> Self {
+        Self {
+            sof: Sof::Request,
+            command: Command::OptionBytes,
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+/// Option bytes loaded at last reset, raw registers are for other option bits
+#[repr(C)]
+pub struct OptionBytesResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub checksum: [u8; 2],
+    pub rdp_level: RdpLevel,
+    /// 1 when every bootloader page is write protected
+    pub wrp_bootloader: u8,
+    pub optr: [u8; 4],   // little endian
+    pub wrp1ar: [u8; 4], // little endian
+    pub wrp1br: [u8; 4], // little endian
+    pub eof: u8,
+}
+
+impl OptionBytesResponseForm {
+    pub fn checksum_source(&self) -> &[u8] {
+        unsafe {
+            let start_ptr = &self.rdp_level as *const RdpLevel as *const u8;
+            let end_ptr = &self.eof as *const u8;
+
+            core::slice::from_raw_parts(start_ptr, end_ptr as usize - start_ptr as usize)
+        }
+    }
+
+    pub fn new<D: Device>(board: &mut Board<D>) -> Self {
+        let option_bytes = board.hardware.option_bytes();
+
+        let mut ret = Self {
+            sof: Sof::Response,
+            command: Command::OptionBytes,
+            checksum: [0; 2],
+            rdp_level: option_bytes.rdp_level(),
+            wrp_bootloader: option_bytes.is_bootloader_protected() as u8,
+            optr: option_bytes.optr.to_le_bytes(),
+            wrp1ar: option_bytes.wrp1ar.to_le_bytes(),
+            wrp1br: option_bytes.wrp1br.to_le_bytes(),
+            eof: EOF_SIGNATURE,
+        };
+
+        let crc = board.hardware.crc();
+        crc.reset();
+        ret.checksum = (crc.feed_bytes(ret.checksum_source()) as u16).to_le_bytes();
+
+        ret
+    }
+}
+
+#[repr(C)]
+pub struct SetBaudRateRequestForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub baudrate: [u8; 4], // little endian
+    pub eof: u8,
+}
+
+impl SetBaudRateRequestForm {
+    #[allow(unused)]
+    pub const fn new(baudrate: u32) -> Self {
+        Self {
+            sof: Sof::Request,
+            command: Command::SetBaudRate,
+            baudrate: baudrate.to_le_bytes(),
+            eof: EOF_SIGNATURE,
+        }
+    }
+
+    /// Requested baudrate when it's supported by the bootloader
+    pub fn baudrate(&self) -> Result<u32, OtaError> {
+        let baudrate = u32::from_le_bytes(self.baudrate);
+        let changeable = capability::CAPABILITIES & capability::CAP_SET_BAUDRATE != 0;
+
+        if changeable && SUPPORTED_BAUDRATES.contains(&baudrate) {
+            Ok(baudrate)
+        } else {
+            Err(OtaError::InvalidArgument)
+        }
+    }
+}
+
+/// Response is sent with previous baudrate, new baudrate is applied right after.
+#[repr(C)]
+pub struct SetBaudRateResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub result: OtaError,
+    pub baudrate: [u8; 4], // little endian
+    pub eof: u8,
+}
+
+impl SetBaudRateResponseForm {
+    pub fn new(result: Result<u32, OtaError>) -> Self {
+        let (result, baudrate) = match result {
+            Ok(baudrate) => (OtaError::Nothing, baudrate),
+            Err(e) => (e, 0),
+        };
+
+        Self {
+            sof: Sof::Response,
+            command: Command::SetBaudRate,
+            result,
+            baudrate: baudrate.to_le_bytes(),
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+/// `StartUpdate` of old host tools, chunk size stays at default and `WriteChunk`
+/// and `UpdateStatus` keep their legacy layout until next `StartUpdate`
+#[repr(C)]
+pub struct LegacyStartUpdateRequestForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub eof: u8,
+}
+
+impl LegacyStartUpdateRequestForm {
+    #[allow(unused)]
+    pub const fn new() -> Self {
+        Self {
+            sof: Sof::Request,
+            command: Command::StartUpdate,
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+#[repr(C)]
+pub struct StartUpdateRequestForm {
+    pub sof: Sof,
+    pub command: Command,
+    /// log2 of the chunk size the host wants to use for `WriteChunk`, never `0xFF`
+    pub payload_exponent: u8,
+    pub eof: u8,
+}
+
+impl StartUpdateRequestForm {
+    #[allow(unused)]
+    pub const fn new(payload_exponent: u8) -> Self {
+        Self {
+            sof: Sof::Request,
+            command: Command::StartUpdate,
+            payload_exponent,
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+/// `StartUpdate` carrying nonce of host, `flags` follows `START_UPDATE_FLAG_*`
+#[repr(C)]
+pub struct StartUpdateNonceRequestForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub payload_exponent: u8,
+    pub flags: u8,
+    pub nonce: [u8; 12],
+    pub eof: u8,
+}
+
+impl StartUpdateNonceRequestForm {
+    #[allow(unused)]
+    pub const fn new(payload_exponent: u8, nonce: [u8; 12]) -> Self {
+        Self {
+            sof: Sof::Request,
+            command: Command::StartUpdate,
+            payload_exponent,
+            flags: START_UPDATE_FLAG_HOST_NONCE,
+            nonce,
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+/// `StartUpdate` of sparse transfer, `flags` has `START_UPDATE_FLAG_IMAGE_LENGTH`
+#[repr(C)]
+pub struct StartUpdateImageRequestForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub payload_exponent: u8,
+    pub flags: u8,
+    /// valid when `flags` has `START_UPDATE_FLAG_HOST_NONCE`
+    pub nonce: [u8; 12],
+    pub image_length: [u8; 4], // little endian
+    pub eof: u8,
+}
+
+impl StartUpdateImageRequestForm {
+    #[allow(unused)]
+    pub const fn new(payload_exponent: u8, nonce: Option<[u8; 12]>, image_length: u32) -> Self {
+        let (flags, nonce) = match nonce {
+            Some(nonce) => (
+                START_UPDATE_FLAG_HOST_NONCE | START_UPDATE_FLAG_IMAGE_LENGTH,
+                nonce,
+            ),
+            None => (START_UPDATE_FLAG_IMAGE_LENGTH, [0; 12]),
+        };
+
+        Self {
+            sof: Sof::Request,
+            command: Command::StartUpdate,
+            payload_exponent,
+            flags,
+            nonce,
+            image_length: image_length.to_le_bytes(),
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+#[repr(C)]
+pub struct StartUpdateResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub checksum: [u8; 2],
+    pub nonce: [u8; 12],
+    /// chunk exponent actually in use, requested one is clamped into supported range
+    pub payload_exponent: u8,
+    /// how many `WriteChunk` host can send before waiting acknowledge
+    pub window_size: u8,
+    pub eof: u8,
+}
+
+impl StartUpdateResponseForm {
+    pub fn checksum_source(&self) -> &[u8] {
+        unsafe {
+            let start_ptr = &self.nonce as *const u8;
+            let end_ptr = &self.eof as *const u8;
+
+            core::slice::from_raw_parts(start_ptr, end_ptr as usize - start_ptr as usize)
+        }
+    }
+
+    /// Application pages up to `image_length` are erased when it's given
+    pub fn new<D: Device>(
+        board: &mut Board<D>,
+        payload_exponent: u8,
+        host_nonce: Option<&[u8; 12]>,
+        image_length: Option<u32>,
+    ) -> Result<Self, OtaError> {
+        if let Some(length) = image_length {
+            erase_image_pages(board, length)?;
+        }
+        board.shared_resource.image_length = image_length;
+
+        let nonce = host_nonce
+            .copied()
+            .unwrap_or_else(|| board.hardware.crypto_nonce());
+        start_transfer(board, &nonce, payload_exponent, false);
+
+        let section_mark = &board.shared_resource.section_mark;
+        let mut ret = Self {
+            sof: Sof::Response,
+            command: Command::StartUpdate,
+            checksum: [0; 2],
+            nonce,
+            payload_exponent: section_mark.chunk_bit_idx,
+            window_size: WriteWindow::window_size(section_mark.chunk_size(), D::UART_RX_BUF_SIZE),
+            eof: EOF_SIGNATURE,
+        };
+
+        let crc = board.hardware.crc();
+        crc.reset();
+        let checksum = crc.feed_bytes(ret.checksum_source());
+
+        ret.checksum = (checksum as u16).to_le_bytes();
+
+        Ok(ret)
+    }
+}
+
+/// Response of legacy `StartUpdate`, checksum covers only the nonce
+#[repr(C)]
+pub struct LegacyStartUpdateResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub checksum: [u8; 2],
+    pub nonce: [u8; 12],
+    pub eof: u8,
+}
+
+impl LegacyStartUpdateResponseForm {
+    pub fn new<D: Device>(board: &mut Board<D>) -> Self {
+        let nonce = board.hardware.crypto_nonce();
+        board.shared_resource.image_length = None;
+        start_transfer(board, &nonce, DEFAULT_CHUNK_BIT_IDX as u8, true);
+
+        let mut ret = Self {
+            sof: Sof::Response,
+            command: Command::StartUpdate,
+            checksum: [0; 2],
+            nonce,
+            eof: EOF_SIGNATURE,
+        };
+
+        let crc = board.hardware.crc();
+        crc.reset();
+        let checksum = crc.feed_bytes(&ret.nonce);
+
+        ret.checksum = (checksum as u16).to_le_bytes();
+
+        ret
+    }
+}
+
+/// Every chunk becomes unwritten, cipher stream restarts with `nonce`
+fn start_transfer<D: Device>(
+    board: &mut Board<D>,
+    nonce: &[u8; 12],
+    payload_exponent: u8,
+    legacy: bool,
+) {
+    let shared_resource = &mut board.shared_resource;
+
+    shared_resource.reset_cipher(nonce);
+    shared_resource.section_mark.reset(payload_exponent);
+    shared_resource.write_window.reset();
+    shared_resource.indicator = Indicator::Receiving;
+    shared_resource.legacy_transfer = legacy;
+}
+
+/// Erase application pages covering `image_length` bytes, takes a while per page
+fn erase_image_pages<D: Device>(board: &mut Board<D>, image_length: u32) -> Result<(), OtaError> {
+    let length = (image_length as usize).div_ceil(region::PAGE_SIZE) * region::PAGE_SIZE;
+    if length == 0 {
+        return Err(OtaError::InvalidArgument);
+    }
+    region::check_erase(REMAIN_OFFSET as u32, length)?;
+
+    board
+        .hardware
+        .flash()
+        .erase(REMAIN_OFFSET as u32, (REMAIN_OFFSET + length) as u32)
+}
+
+/// Variable-length request, `tail` holds the payload of negotiated chunk size
+/// followed by EOF signature.
+#[repr(C)]
+pub struct WriteChunkRequestForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub checksum: [u8; 2], // little endian
+    pub sequence: [u8; 2], // little endian
+    pub flags: u8,
+    pub offset: [u8; 4], // little endian
+    pub tail: [u8],
+}
+
+impl WriteChunkRequestForm {
+    /// `arr` should be already tested with `test_packet`
+    unsafe fn from_raw(arr: &[u8]) -> &Self {
+        let tail_len = arr.len() - write_chunk_request_size(0) + 1;
+        &*(core::ptr::slice_from_raw_parts(arr.as_ptr(), tail_len) as *const Self)
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.tail[..self.tail.len() - 1]
+    }
+
+    pub fn checksum_source(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(self.sequence.as_ptr(), 2 + 1 + 4 + self.payload().len())
+        }
+    }
+
+    /// Serialize request into `buf` for host side usage, returns written length
+    // #[cfg(any(not(feature = "no_std"), feature = "std", test))]
+    #[allow(unused)]
+    pub fn new_std(
+        sequence: u16,
+        flags: u8,
+        offset: u32,
+        bytes: &[u8],
+        buf: &mut [u8],
+    ) -> Result<usize, OtaError> {
+        // if offset + bytes.len() as u32 > size {
+        //     return Err(Error::Size);
+        // }
+        if offset % WRITE_SIZE as u32 != 0 || bytes.len() % WRITE_SIZE != 0 {
+            return Err(OtaError::FlashUnaligned);
+        }
+        let len = write_chunk_request_size(bytes.len());
+        if buf.len() < len {
+            return Err(OtaError::OutOfRange);
+        }
+
+        buf[0] = Sof::Request as u8;
+        buf[1] = Command::WriteChunk as u8;
+        buf[4..6].copy_from_slice(&sequence.to_le_bytes());
+        buf[6] = flags;
+        buf[7..11].copy_from_slice(&offset.to_le_bytes());
+        buf[11..len - 1].copy_from_slice(bytes);
+        buf[len - 1] = EOF_SIGNATURE;
+
+        let checksum = crate::types::std_crc::std_crc(&buf[4..len - 1]) as u16;
+        buf[2..4].copy_from_slice(&checksum.to_le_bytes());
+
+        Ok(len)
+    }
+
+    fn verify<D: Device>(&self, board: &mut Board<D>) -> Result<(), OtaError> {
+        verify_chunk(
+            board,
+            self.checksum,
+            self.checksum_source(),
+            u32::from_le_bytes(self.offset),
+        )
+    }
+
+    /// Handle chunk with sequence tracking, returns response only when host
+    /// asked acknowledge or chunk is rejected. Broadcast chunk is unsequenced.
+    pub(crate) fn process<D: Device>(
+        &self,
+        board: &mut Board<D>,
+        broadcast: bool,
+    ) -> Option<WriteChunkResponseForm> {
+        let sequence = u16::from_le_bytes(self.sequence);
+        let ack_requested = (self.flags & WRITE_CHUNK_FLAG_ACK) != 0;
+        let unsequenced = broadcast || (self.flags & WRITE_CHUNK_FLAG_UNSEQUENCED) != 0;
+
+        let result = match self.verify(board) {
+            Err(e) => Err(e),
+            // flash can't be programmed twice, written one is just acknowledged
+            Ok(_) if unsequenced => {
+                flash_unwritten_chunk(board, u32::from_le_bytes(self.offset), self.payload())
+            }
+            Ok(_) => match board.shared_resource.write_window.check(sequence) {
+                SequenceCheck::Duplicate => Ok(()),
+                SequenceCheck::Gap => match board.shared_resource.write_window.report_gap() {
+                    Some(e) => Err(e),
+                    None if ack_requested => Err(OtaError::SequenceGap),
+                    None => return None,
+                },
+                SequenceCheck::InOrder => {
+                    flash_chunk(board, u32::from_le_bytes(self.offset), self.payload())
+                        .map(|_| board.shared_resource.write_window.advance())
+                }
+            },
+        };
+
+        if result.is_ok() && !ack_requested {
+            return None;
+        }
+
+        Some(WriteChunkResponseForm::new(
+            result,
+            sequence,
+            board.shared_resource.write_window.next_sequence,
+        ))
+    }
+}
+
+/// Checksum of the request, then whether chunk at `offset` lies in application region
+fn verify_chunk<D: Device>(
+    board: &mut Board<D>,
+    checksum: [u8; 2],
+    checksum_source: &[u8],
+    offset: u32,
+) -> Result<(), OtaError> {
+    let crc = board.hardware.crc();
+
+    crc.reset();
+    let actual = crc.feed_bytes(checksum_source) as u16;
+
+    let expected = u16::from_le_bytes(checksum);
+    if actual != expected {
+        return Err(OtaError::ChecksumError);
+    }
+
+    region::check_chunk(offset, board.shared_resource.section_mark.chunk_size())
+}
+
+/// Flash can't be programmed twice, chunk already written is just acknowledged
+fn flash_unwritten_chunk<D: Device>(
+    board: &mut Board<D>,
+    address: u32,
+    payload: &[u8],
+) -> Result<(), OtaError> {
+    if board.shared_resource.section_mark.is_marked(address) {
+        Ok(())
+    } else {
+        flash_chunk(board, address, payload)
+    }
+}
+
+/// Decrypt and program chunk at `address`, then read it back
+fn flash_chunk<D: Device>(
+    board: &mut Board<D>,
+    address: u32,
+    payload: &[u8],
+) -> Result<(), OtaError> {
+    // keystream position follows the offset, so resent chunks decrypt the same way
+    let cipher = &mut board.shared_resource.cipher;
+    cipher.seek(address - REMAIN_OFFSET as u32);
+
+    // decrypted and programmed piece by piece, whole chunk is never copied on stack
+    let flash = board.hardware.flash();
+    let mut buf = [0u8; MIN_WRITE_CHUNK_SIZE];
+    let written = payload
+        .chunks(buf.len())
+        .enumerate()
+        .try_for_each(|(i, piece)| {
+            let offset = address + (i * MIN_WRITE_CHUNK_SIZE) as u32;
+            let data = &mut buf[..piece.len()];
+            data.copy_from_slice(piece);
+            cipher.apply_keystream(data); // decrypt
+
+            flash
+                .write(offset, data)
+                .and_then(|_| read_back(flash, offset, data))
+        });
+
+    // latched until next `StartUpdate`, later chunks don't hide it
+    if let Err(e) = written {
+        board.shared_resource.indicator = Indicator::VerifyFailed;
+        return Err(e);
+    }
+
+    board.shared_resource.section_mark.mark_offset(address);
+
+    Ok(())
+}
+
+/// Compare programmed flash at `offset` with `expected`, marginal cells may
+/// pass programming but read back different
+fn read_back(flash: &mut impl OtaFlash, offset: u32, expected: &[u8]) -> Result<(), OtaError> {
+    region::check_read(offset, expected.len())?;
+
+    let mut buf = [0u8; MIN_WRITE_CHUNK_SIZE];
+
+    for (i, expected) in expected.chunks(buf.len()).enumerate() {
+        let actual = &mut buf[..expected.len()];
+        flash.read(offset + (i * MIN_WRITE_CHUNK_SIZE) as u32, actual)?;
+
+        if actual != expected {
+            return Err(OtaError::FlashVerify);
+        }
+    }
+
+    Ok(())
+}
+
+/// Every byte of `len` at `offset` is `0xFF`
+fn is_erased(flash: &mut impl OtaFlash, offset: u32, len: usize) -> Result<bool, OtaError> {
+    region::check_read(offset, len)?;
+
+    let mut buf = [0u8; MIN_WRITE_CHUNK_SIZE];
+
+    for start in (0..len).step_by(buf.len()) {
+        let actual = &mut buf[..(len - start).min(MIN_WRITE_CHUNK_SIZE)];
+        flash.read(offset + start as u32, actual)?;
+
+        if actual.iter().any(|&x| x != 0xFF) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// `WriteChunk` of legacy transfer, default chunk size without sequence and flags
+#[repr(C)]
+pub struct LegacyWriteChunkRequestForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub checksum: [u8; 2], // little endian
+    pub offset: [u8; 4],   // little endian
+    pub payload: [u8; DEFAULT_WRITE_CHUNK_SIZE],
+    pub eof: u8,
+}
+
+impl LegacyWriteChunkRequestForm {
+    pub fn checksum_source(&self) -> &[u8] {
+        unsafe {
+            let start_ptr = &self.offset as *const u8;
+            let end_ptr = &self.eof as *const u8;
+
+            core::slice::from_raw_parts(start_ptr, end_ptr as usize - start_ptr as usize)
+        }
+    }
+
+    // #[cfg(any(not(feature = "no_std"), feature = "std", test))]
+    #[allow(unused)]
+    pub fn new_std(offset: u32, bytes: &[u8; DEFAULT_WRITE_CHUNK_SIZE]) -> Result<Self, OtaError> {
+        if offset % WRITE_SIZE as u32 != 0 {
+            return Err(OtaError::FlashUnaligned);
+        }
+        let mut ret = Self {
+            sof: Sof::Request,
+            command: Command::WriteChunk,
+            checksum: [0; 2],
+            offset: offset.to_le_bytes(),
+            payload: *bytes,
+            eof: EOF_SIGNATURE,
+        };
+
+        ret.checksum = (crate::types::std_crc::std_crc(ret.checksum_source()) as u16).to_le_bytes();
+
+        Ok(ret)
+    }
+
+    /// Written by its offset like unsequenced chunk, every chunk is answered
+    pub(crate) fn process<D: Device>(&self, board: &mut Board<D>) -> LegacyWriteChunkResponseForm {
+        let offset = u32::from_le_bytes(self.offset);
+        let result = verify_chunk(board, self.checksum, self.checksum_source(), offset)
+            .and_then(|_| flash_unwritten_chunk(board, offset, &self.payload));
+
+        LegacyWriteChunkResponseForm::new(result)
+    }
+}
+
+#[repr(C)]
+pub struct LegacyWriteChunkResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub result: OtaError,
+    pub eof: u8,
+}
+
+impl LegacyWriteChunkResponseForm {
+    pub fn new(result: Result<(), OtaError>) -> Self {
+        Self {
+            sof: Sof::Response,
+            command: Command::WriteChunk,
+            result: result.map_or_else(|e| e, |_| OtaError::Nothing),
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+/// Acknowledge (`result` is `Nothing`) or NACK of `sequence`,
+/// `next_sequence` is cumulative, every chunk before it is written.
+#[repr(C)]
+pub struct WriteChunkResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub result: OtaError,
+    pub sequence: [u8; 2],      // little endian
+    pub next_sequence: [u8; 2], // little endian
+    pub eof: u8,
+}
+
+impl WriteChunkResponseForm {
+    pub fn new(result: Result<(), OtaError>, sequence: u16, next_sequence: u16) -> Self {
+        Self {
+            sof: Sof::Response,
+            command: Command::WriteChunk,
+            result: result.map_or_else(|e| e, |_| OtaError::Nothing),
+            sequence: sequence.to_le_bytes(),
+            next_sequence: next_sequence.to_le_bytes(),
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+/// Guards `SetProtection` against a stray or corrupted request
+pub const SET_PROTECTION_KEY: [u8; 4] = *b"LOCK";
+
+/// Apply `PROTECT_FLAG_*` of `option_bytes`, device reloads option bytes
+/// (resets) after the response. Meant as the last step of production.
+#[repr(C)]
+pub struct SetProtectionRequestForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub flags: u8,
+    /// `SET_PROTECTION_KEY`
+    pub key: [u8; 4],
+    pub eof: u8,
+}
+
+impl SetProtectionRequestForm {
+    #[allow(unused)]
+    pub const fn new(flags: u8) -> Self {
+        Self {
+            sof: Sof::Request,
+            command: Command::SetProtection,
+            flags,
+            key: SET_PROTECTION_KEY,
+            eof: EOF_SIGNATURE,
+        }
+    }
+
+    /// Program option bytes, reloading them is left to the caller
+    pub fn apply<D: Device>(&self, board: &mut Board<D>) -> Result<(), OtaError> {
+        if self.key != SET_PROTECTION_KEY {
+            return Err(OtaError::InvalidArgument);
+        }
+
+        board.hardware.program_option_bytes(self.flags)
+    }
+}
+
+#[repr(C)]
+pub struct SetProtectionResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub result: OtaError,
+    pub flags: u8,
+    pub eof: u8,
+}
+
+impl SetProtectionResponseForm {
+    pub fn new(result: Result<(), OtaError>, flags: u8) -> Self {
+        Self {
+            sof: Sof::Response,
+            command: Command::SetProtection,
+            result: result.map_or_else(|e| e, |_| OtaError::Nothing),
+            flags,
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+/// Generic rejection of request frame (checksum, unknown command, missing SOF/EOF ...)
+#[repr(C)]
+pub struct NackResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub result: OtaError,
+    /// command byte of rejected request, 0 when it's unknown
+    pub rejected_command: u8,
+    pub eof: u8,
+}
+
+impl NackResponseForm {
+    pub fn new(result: OtaError, rejected_command: Option<u8>) -> Self {
+        Self {
+            sof: Sof::Response,
+            command: Command::Nack,
+            result,
+            rejected_command: rejected_command.unwrap_or(0),
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+#[repr(C)]
+pub struct UpdateStatusRequestForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub eof: u8,
+}
+
+impl UpdateStatusRequestForm {
+    #[allow(unused)]
+    pub const fn new() -> Self {
+        Self {
+            sof: Sof::Response,
+            command: Command::UpdateStatus,
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+// acutal MCU will use above form
+#[repr(C)]
+pub struct UpdateStatusResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub checksum: [u8; 2],
+    pub chunk_mark: SectionMark,
+    pub eof: u8,
+}
+
+impl UpdateStatusResponseForm {
+    pub fn new<D: Device>(board: &mut Board<D>) -> Self {
+        let mut ret: Self = Self {
+            sof: Sof::Response,
+            command: Command::UpdateStatus,
+            checksum: [0; 2],
+            chunk_mark: board.shared_resource.section_mark.clone(),
+            eof: EOF_SIGNATURE,
+        };
+
+        let crc = board.hardware.crc();
+        crc.reset();
+        let checksum = crc.feed_bytes(&ret.chunk_mark.bitmap);
+
+        ret.checksum = (checksum as u16).to_le_bytes();
+
+        ret
+    }
+}
+
+/// `UpdateStatus` of legacy transfer, bit per default chunk and nothing after bitmap
+#[repr(C)]
+pub struct LegacyUpdateStatusResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub checksum: [u8; 2],
+    pub bitmap: [u8; LEGACY_BITMAP_SIZE],
+    pub eof: u8,
+}
+
+impl LegacyUpdateStatusResponseForm {
+    pub fn new<D: Device>(board: &mut Board<D>) -> Self {
+        let mut ret: Self = Self {
+            sof: Sof::Response,
+            command: Command::UpdateStatus,
+            checksum: [0; 2],
+            bitmap: [0; LEGACY_BITMAP_SIZE],
+            eof: EOF_SIGNATURE,
+        };
+        // legacy transfer always runs with default chunk size
+        ret.bitmap
+            .copy_from_slice(&board.shared_resource.section_mark.bitmap[..LEGACY_BITMAP_SIZE]);
+
+        let crc = board.hardware.crc();
+        crc.reset();
+        let checksum = crc.feed_bytes(&ret.bitmap);
+
+        ret.checksum = (checksum as u16).to_le_bytes();
+
+        ret
+    }
+}
+
+/// End of transfer, `image_length` must match the one of sparse `StartUpdate`
+#[repr(C)]
+pub struct FinalizeRequestForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub image_length: [u8; 4], // little endian
+    pub eof: u8,
+}
+
+impl FinalizeRequestForm {
+    #[allow(unused)]
+    pub const fn new(image_length: u32) -> Self {
+        Self {
+            sof: Sof::Request,
+            command: Command::Finalize,
+            image_length: image_length.to_le_bytes(),
+            eof: EOF_SIGNATURE,
+        }
+    }
+}
+
+/// `erased_chunks` are the ones taken as omitted by host, host compares it with
+/// how many it skipped. `result` is `FlashVerify` when image doesn't pass the check.
+#[repr(C)]
+pub struct FinalizeResponseForm {
+    pub sof: Sof,
+    pub command: Command,
+    pub checksum: [u8; 2],
+    pub result: OtaError,
+    pub app_status: AppStatus,
+    pub erased_chunks: [u8; 2], // little endian
+    pub eof: u8,
+}
+
+impl FinalizeResponseForm {
+    pub fn checksum_source(&self) -> &[u8] {
+        unsafe {
+            let start_ptr = &self.result as *const OtaError as *const u8;
+            let end_ptr = &self.eof as *const u8;
+
+            core::slice::from_raw_parts(start_ptr, end_ptr as usize - start_ptr as usize)
+        }
+    }
+
+    pub fn new<D: Device>(board: &mut Board<D>, image_length: u32) -> Self {
+        let (result, erased_chunks) = match Self::mark_erased_chunks(board, image_length) {
+            Ok(erased_chunks) => (Ok(()), erased_chunks),
+            Err(e) => (Err(e), 0),
+        };
+
+        let info = AppInfo::inspect(&mut board.hardware);
+        let result = result.and_then(|_| {
+            if info.is_bootable() {
+                Ok(())
+            } else {
+                Err(OtaError::FlashVerify)
+            }
+        });
+
+        // failed chunk stays latched even when the image passes
+        if result.is_err() || board.shared_resource.indicator == Indicator::VerifyFailed {
+            board.shared_resource.indicator = Indicator::VerifyFailed;
+        } else {
+            board.shared_resource.indicator = Indicator::Done;
+        }
+
+        let mut ret = Self {
+            sof: Sof::Response,
+            command: Command::Finalize,
+            checksum: [0; 2],
+            result: result.map_or_else(|e| e, |_| OtaError::Nothing),
+            app_status: info.status,
+            erased_chunks: erased_chunks.to_le_bytes(),
+            eof: EOF_SIGNATURE,
+        };
+
+        let crc = board.hardware.crc();
+        crc.reset();
+        ret.checksum = (crc.feed_bytes(ret.checksum_source()) as u16).to_le_bytes();
+
+        ret
+    }
+
+    /// Mark unwritten chunks still erased up to `image_length` as complete,
+    /// returns how many of them are marked
+    fn mark_erased_chunks<D: Device>(
+        board: &mut Board<D>,
+        image_length: u32,
+    ) -> Result<u16, OtaError> {
+        // only after `StartUpdate` with the same image length
+        if board.shared_resource.image_length != Some(image_length) {
+            return Err(OtaError::InvalidArgument);
+        }
+        region::check_read(REMAIN_OFFSET as u32, image_length as usize)?;
+
+        let section_mark = &mut board.shared_resource.section_mark;
+        let flash = board.hardware.flash();
+        let chunk_size = section_mark.chunk_size();
+        let end = REMAIN_OFFSET + image_length as usize;
+        let mut erased_chunks = 0;
+
+        for offset in (REMAIN_OFFSET..end).step_by(chunk_size) {
+            let offset = offset as u32;
+
+            if !section_mark.is_marked(offset) && is_erased(flash, offset, chunk_size)? {
+                section_mark.mark_offset(offset);
+                erased_chunks += 1;
+            }
+        }
+
+        Ok(erased_chunks)
+    }
+}
+
 #[repr(C)]
 pub struct ResetForm {
     pub sof: Sof,
@@ -0,0 +1,59 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Where the host may touch flash. Every offset given by host goes through here
+//! before flash access, so the bootloader can't be overwritten by OTA.
+//! Offsets are relative to `FLASH_BASE`, same as `WriteChunk` offset.
+
+use super::ota::OtaError;
+use super::section_mark::{FLASH_SIZE, REMAIN_OFFSET};
+
+/// Double-word programming of STM32G0
+pub const WRITE_SIZE: usize = 8;
+pub const PAGE_SIZE: usize = 2048;
+
+#[cfg(target_arch = "arm")]
+static_assertions::const_assert_eq!(WRITE_SIZE, embassy_stm32::flash::WRITE_SIZE);
+#[cfg(target_arch = "arm")]
+static_assertions::const_assert_eq!(
+    PAGE_SIZE,
+    embassy_stm32::flash::BANK1_REGION.erase_size as usize
+);
+
+/// `len` bytes at `offset` is in application region,
+/// `align` is counted from application start
+const fn check(offset: u32, len: usize, align: usize) -> Result<(), OtaError> {
+    let offset = offset as usize;
+
+    if offset < REMAIN_OFFSET || offset > FLASH_SIZE || len > FLASH_SIZE - offset {
+        return Err(OtaError::OutOfRegion);
+    }
+    if (offset - REMAIN_OFFSET) % align != 0 || len % align != 0 {
+        return Err(OtaError::FlashUnaligned);
+    }
+
+    Ok(())
+}
+
+/// Chunk of `chunk_size` at `offset`, `chunk_size` is power of 2 not smaller than `WRITE_SIZE`
+pub const fn check_chunk(offset: u32, chunk_size: usize) -> Result<(), OtaError> {
+    check(offset, chunk_size, chunk_size)
+}
+
+/// Program `len` bytes at `offset`
+pub const fn check_write(offset: u32, len: usize) -> Result<(), OtaError> {
+    check(offset, len, WRITE_SIZE)
+}
+
+/// Erase pages of `len` bytes from `offset`
+pub const fn check_erase(offset: u32, len: usize) -> Result<(), OtaError> {
+    check(offset, len, PAGE_SIZE)
+}
+
+/// Read `len` bytes from `offset`
+pub const fn check_read(offset: u32, len: usize) -> Result<(), OtaError> {
+    check(offset, len, 1)
+}
@@ -5,43 +5,110 @@
  */
 
 pub(crate) const BOOTLOADER_ORIGIN: usize = env_to_array::hex_env_to_usize!("FLASH_ORIGIN");
-const BOOTLOADER_LENGTH: usize = env_to_array::hex_env_to_usize!("FLASH_LENGTH");
-const FLASH_BASE: usize = embassy_stm32::flash::FLASH_BASE;
-const FLASH_SIZE: usize = embassy_stm32::flash::FLASH_SIZE;
+pub(crate) const BOOTLOADER_LENGTH: usize = env_to_array::hex_env_to_usize!("FLASH_LENGTH");
+pub(crate) const FLASH_BASE: usize = laplus_boots_app::FLASH_BASE;
+pub(crate) const FLASH_SIZE: usize = laplus_boots_app::FLASH_SIZE;
 
 pub const REMAIN_OFFSET: usize = BOOTLOADER_ORIGIN + BOOTLOADER_LENGTH - FLASH_BASE;
-const REMAIN_SIZE: usize = FLASH_SIZE - REMAIN_OFFSET;
+pub(crate) const REMAIN_SIZE: usize = FLASH_SIZE - REMAIN_OFFSET;
+
+// application finds bootloader and itself with these through `laplus_boots_app`
+static_assertions::const_assert_eq!(BOOTLOADER_ORIGIN, laplus_boots_app::BOOTLOADER_ORIGIN);
+static_assertions::const_assert_eq!(REMAIN_OFFSET, laplus_boots_app::REMAIN_OFFSET);
+// host tests have no embassy-stm32, flash geometry is taken from `laplus_boots_app`
+#[cfg(target_arch = "arm")]
+static_assertions::const_assert_eq!(FLASH_BASE, embassy_stm32::flash::FLASH_BASE);
+#[cfg(target_arch = "arm")]
+static_assertions::const_assert_eq!(FLASH_SIZE, embassy_stm32::flash::FLASH_SIZE);
+
+/// Smallest chunk the host can negotiate on `StartUpdate` (64 bytes)
+pub const MIN_CHUNK_BIT_IDX: usize = 6;
+/// Largest chunk the host can negotiate on `StartUpdate` (1024 bytes), bounded by RAM
+pub const MAX_CHUNK_BIT_IDX: usize = 10;
+/// Chunk exponent in use until the host negotiates another one (256 bytes)
+pub const DEFAULT_CHUNK_BIT_IDX: usize = 8;
+
+pub const MIN_WRITE_CHUNK_SIZE: usize = 1 << MIN_CHUNK_BIT_IDX;
+pub const MAX_WRITE_CHUNK_SIZE: usize = 1 << MAX_CHUNK_BIT_IDX;
+pub const DEFAULT_WRITE_CHUNK_SIZE: usize = 1 << DEFAULT_CHUNK_BIT_IDX;
 
-pub const WRITE_CHUNK_SIZE: usize = 256;
-pub const CHUNK_BIT_IDX: usize = WRITE_CHUNK_SIZE.trailing_zeros() as usize;
 const BYTE_BIT_IDX: usize = 8_u8.trailing_zeros() as usize;
-const MAX_PAGE: usize = REMAIN_SIZE / WRITE_CHUNK_SIZE;
+// bitmap is sized for the smallest chunk, larger chunks just use less of it
+const MAX_PAGE: usize = REMAIN_SIZE / MIN_WRITE_CHUNK_SIZE;
 const PAGE_BITMAP_SIZE: usize = (MAX_PAGE + 7) / 8;
+/// Bitmap of legacy `UpdateStatus`, sized for the default chunk as it always was
+pub const LEGACY_BITMAP_SIZE: usize = (REMAIN_SIZE / DEFAULT_WRITE_CHUNK_SIZE + 7) / 8;
 
+/// Sent as is by `UpdateStatus`, `bitmap` comes first as it always did
+/// and `chunk_bit_idx` follows it.
 #[repr(C)]
 #[derive(Clone, PartialEq, Eq)]
 pub struct SectionMark {
     pub bitmap: [u8; PAGE_BITMAP_SIZE],
+    /// log2 of the chunk size each bit of `bitmap` stands for
+    pub chunk_bit_idx: u8,
 }
 
+// host tools read the bitmap right after `UpdateStatus` checksum
+static_assertions::const_assert_eq!(core::mem::offset_of!(SectionMark, bitmap), 0);
+
 impl SectionMark {
     pub fn new() -> Self {
         Self {
             bitmap: [0u8; PAGE_BITMAP_SIZE],
+            chunk_bit_idx: DEFAULT_CHUNK_BIT_IDX as u8,
+        }
+    }
+
+    /// Clamp requested chunk exponent into what this bootloader can buffer
+    pub const fn clamp_chunk_bit_idx(requested: u8) -> u8 {
+        if (requested as usize) < MIN_CHUNK_BIT_IDX {
+            MIN_CHUNK_BIT_IDX as u8
+        } else if (requested as usize) > MAX_CHUNK_BIT_IDX {
+            MAX_CHUNK_BIT_IDX as u8
+        } else {
+            requested
         }
     }
 
+    /// Start over with a new chunk size, every chunk becomes unmarked
+    pub fn reset(&mut self, chunk_bit_idx: u8) {
+        self.chunk_bit_idx = Self::clamp_chunk_bit_idx(chunk_bit_idx);
+        self.clear();
+    }
+
+    #[inline]
+    pub const fn chunk_size(&self) -> usize {
+        1 << self.chunk_bit_idx
+    }
+
+    /// Bit index of chunk at `offset`, `None` when it's out of application region
+    fn chunk_index(&self, offset: u32) -> Option<usize> {
+        let relative = (offset as usize).checked_sub(REMAIN_OFFSET)?;
+
+        (relative < REMAIN_SIZE).then_some(relative >> self.chunk_bit_idx)
+    }
+
+    /// Offset out of application region is ignored
     pub fn mark_offset(&mut self, offset: u32) {
-        let p = offset - (REMAIN_OFFSET as u32);
-        self.bitmap[(p as usize) >> (CHUNK_BIT_IDX + BYTE_BIT_IDX)] |=
-            1 << ((p >> CHUNK_BIT_IDX) & 0x7);
+        if let Some(p) = self.chunk_index(offset) {
+            self.bitmap[p >> BYTE_BIT_IDX] |= 1 << (p & 0x7);
+        }
+    }
+
+    /// Offset out of application region is never marked
+    pub fn is_marked(&self, offset: u32) -> bool {
+        match self.chunk_index(offset) {
+            Some(p) => (self.bitmap[p >> BYTE_BIT_IDX] & (1 << (p & 0x7))) != 0,
+            None => false,
+        }
     }
 
     #[allow(unused)]
     pub fn unmark_offset(&mut self, offset: u32) {
-        let p = offset - (REMAIN_OFFSET as u32);
-        self.bitmap[(p as usize) >> (CHUNK_BIT_IDX + BYTE_BIT_IDX)] &=
-            !(1 << ((p >> CHUNK_BIT_IDX) & 0x7));
+        if let Some(p) = self.chunk_index(offset) {
+            self.bitmap[p >> BYTE_BIT_IDX] &= !(1 << (p & 0x7));
+        }
     }
 
     #[allow(unused)]
@@ -60,3 +127,62 @@ impl SectionMark {
         ret as usize
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_in_region_is_marked() {
+        let mut mark = SectionMark::new();
+        let offset = (REMAIN_OFFSET + 3 * mark.chunk_size()) as u32;
+
+        mark.mark_offset(offset);
+
+        assert!(mark.is_marked(offset));
+        assert!(!mark.is_marked(offset - mark.chunk_size() as u32));
+        assert_eq!(mark.popcount(), PAGE_BITMAP_SIZE * 8 - 1);
+    }
+
+    #[test]
+    fn offset_before_region_is_ignored() {
+        let mut mark = SectionMark::new();
+
+        for offset in [0, REMAIN_OFFSET as u32 - 1] {
+            mark.mark_offset(offset);
+            assert!(!mark.is_marked(offset));
+        }
+        assert_eq!(mark.popcount(), PAGE_BITMAP_SIZE * 8);
+    }
+
+    #[test]
+    fn offset_after_region_is_ignored() {
+        let mut mark = SectionMark::new();
+
+        // larger chunks leave spare bits in the bitmap, those must stay unreachable
+        for chunk_bit_idx in [MIN_CHUNK_BIT_IDX, DEFAULT_CHUNK_BIT_IDX, MAX_CHUNK_BIT_IDX] {
+            mark.reset(chunk_bit_idx as u8);
+
+            for offset in [
+                FLASH_SIZE as u32,
+                (FLASH_SIZE + mark.chunk_size()) as u32,
+                u32::MAX,
+            ] {
+                mark.mark_offset(offset);
+                assert!(!mark.is_marked(offset));
+            }
+            assert_eq!(mark.popcount(), PAGE_BITMAP_SIZE * 8);
+        }
+    }
+
+    #[test]
+    fn larger_chunk_keeps_last_chunk_in_bitmap() {
+        let mut mark = SectionMark::new();
+        mark.reset(MAX_CHUNK_BIT_IDX as u8);
+        let last = (FLASH_SIZE - mark.chunk_size()) as u32;
+
+        mark.mark_offset(last);
+
+        assert!(mark.is_marked(last));
+    }
+}
@@ -0,0 +1,147 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Sequence tracking for windowed `WriteChunk` streaming.
+//! Host may send up to `window_size` chunks back-to-back and only asks for
+//! acknowledge on the last one. Chunks are accepted strictly in sequence order
+//! (go-back-N), a gap is reported once with a NACK carrying `next_sequence`
+//! so the host can resend from there.
+
+use super::ota::{write_chunk_request_size, OtaError};
+
+/// Upper bound of chunks in flight regardless of chunk size
+pub const MAX_WINDOW_SIZE: usize = 8;
+
+/// Outcome of comparing received sequence with expected one
+pub enum SequenceCheck {
+    /// Chunk is the next one, should be written
+    InOrder,
+    /// Chunk was already written, just acknowledge
+    Duplicate,
+    /// Chunk(s) before this one were lost
+    Gap,
+}
+
+#[derive(Clone, Copy)]
+pub struct WriteWindow {
+    /// every chunk before this sequence is written
+    pub next_sequence: u16,
+    /// gap NACK is already sent for current `next_sequence`
+    gap_reported: bool,
+}
+
+impl WriteWindow {
+    pub const fn new() -> Self {
+        Self {
+            next_sequence: 0,
+            gap_reported: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// How many chunks host can send without waiting acknowledge,
+    /// bounded by what UART RX buffer can hold while flash is busy.
+    pub const fn window_size(chunk_size: usize, uart_rx_buf_size: usize) -> u8 {
+        let fit = uart_rx_buf_size / write_chunk_request_size(chunk_size);
+
+        if fit == 0 {
+            1
+        } else if fit > MAX_WINDOW_SIZE {
+            MAX_WINDOW_SIZE as u8
+        } else {
+            fit as u8
+        }
+    }
+
+    pub fn check(&self, sequence: u16) -> SequenceCheck {
+        match sequence.wrapping_sub(self.next_sequence) {
+            0 => SequenceCheck::InOrder,
+            d if d > u16::MAX / 2 => SequenceCheck::Duplicate,
+            _ => SequenceCheck::Gap,
+        }
+    }
+
+    pub fn advance(&mut self) {
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        self.gap_reported = false;
+    }
+
+    /// Returns error only at first time of the gap, to not flood the host with NACKs
+    pub fn report_gap(&mut self) -> Option<OtaError> {
+        if self.gap_reported {
+            None
+        } else {
+            self.gap_reported = true;
+            Some(OtaError::SequenceGap)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequence_in_order_duplicate_and_gap() {
+        let mut window = WriteWindow::new();
+        window.advance();
+        window.advance();
+
+        assert!(matches!(window.check(2), SequenceCheck::InOrder));
+        assert!(matches!(window.check(1), SequenceCheck::Duplicate));
+        assert!(matches!(window.check(0), SequenceCheck::Duplicate));
+        assert!(matches!(window.check(3), SequenceCheck::Gap));
+        assert!(matches!(
+            window.check(2 + MAX_WINDOW_SIZE as u16),
+            SequenceCheck::Gap
+        ));
+    }
+
+    #[test]
+    fn sequence_wraps_around() {
+        let mut window = WriteWindow::new();
+        window.next_sequence = u16::MAX;
+
+        assert!(matches!(window.check(u16::MAX), SequenceCheck::InOrder));
+        assert!(matches!(window.check(0), SequenceCheck::Gap));
+
+        window.advance();
+
+        assert_eq!(window.next_sequence, 0);
+        assert!(matches!(window.check(0), SequenceCheck::InOrder));
+        assert!(matches!(window.check(u16::MAX), SequenceCheck::Duplicate));
+        assert!(matches!(
+            window.check(u16::MAX - 1),
+            SequenceCheck::Duplicate
+        ));
+    }
+
+    #[test]
+    fn gap_is_reported_once_until_advance() {
+        let mut window = WriteWindow::new();
+
+        assert!(matches!(window.report_gap(), Some(OtaError::SequenceGap)));
+        assert!(window.report_gap().is_none());
+        assert!(window.report_gap().is_none());
+
+        window.advance();
+
+        assert!(matches!(window.report_gap(), Some(OtaError::SequenceGap)));
+    }
+
+    #[test]
+    fn window_is_bounded_by_rx_buffer() {
+        let request = write_chunk_request_size(256);
+
+        assert_eq!(WriteWindow::window_size(256, 0), 1);
+        assert_eq!(WriteWindow::window_size(256, request - 1), 1);
+        assert_eq!(WriteWindow::window_size(256, 3 * request), 3);
+        assert_eq!(WriteWindow::window_size(64, 1 << 16), MAX_WINDOW_SIZE as u8);
+    }
+}
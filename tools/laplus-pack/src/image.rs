@@ -0,0 +1,223 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Flat application image out of ELF or raw binary, image header filled in.
+
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use laplus_boots_app::header::{
+    compute_image_crc, AppHeader, APP_HEADER_MAGIC, APP_HEADER_OFFSET, APP_HEADER_SIZE,
+    APP_HEADER_VERSION,
+};
+use laplus_boots_app::{FLASH_BASE, REMAIN_OFFSET, REMAIN_SIZE};
+use object::elf::PT_LOAD;
+use object::read::elf::{ElfFile32, ProgramHeader};
+use object::{Endianness, Object, ObjectSection};
+
+/// Application start address in flash
+const APP_BASE: u64 = (FLASH_BASE + REMAIN_OFFSET) as u64;
+/// Image is padded to the smallest `WriteChunk` size with erased bytes
+pub const PAD_SIZE: usize = 64;
+const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+const FINGERPRINT_SECTION: &str = ".mp_fingerprint";
+
+pub struct AppImage {
+    /// From application start, padded to `PAD_SIZE`
+    pub bytes: Vec<u8>,
+    pub header: AppHeader,
+    /// Content of `.mp_fingerprint` section when input is ELF
+    pub fingerprint: Option<String>,
+}
+
+impl AppImage {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+
+        Self::from_data(data)
+    }
+
+    /// ELF or raw binary already in memory
+    pub fn from_data(data: Vec<u8>) -> Result<Self> {
+        let (bytes, fingerprint) = if data.starts_with(&ELF_MAGIC) {
+            from_elf(&data)?
+        } else {
+            (data, None)
+        };
+
+        let (bytes, header) = fill_header(bytes)?;
+
+        Ok(Self {
+            bytes,
+            header,
+            fingerprint,
+        })
+    }
+}
+
+/// Loadable segments placed by their load address, gaps are erased bytes
+fn from_elf(data: &[u8]) -> Result<(Vec<u8>, Option<String>)> {
+    let elf = ElfFile32::<Endianness>::parse(data).context("parsing ELF")?;
+    let endian = elf.endian();
+    let mut bytes = Vec::new();
+
+    for segment in elf.elf_program_headers() {
+        let content = segment
+            .data(endian, data)
+            .map_err(|_| anyhow::anyhow!("broken ELF segment"))?;
+        if segment.p_type(endian) != PT_LOAD || content.is_empty() {
+            continue;
+        }
+
+        let address = segment.p_paddr(endian) as u64;
+        ensure!(
+            address >= APP_BASE,
+            "segment at {address:#010x} is below application start {APP_BASE:#010x}, \
+             is it linked for the bootloader?"
+        );
+
+        let start = (address - APP_BASE) as usize;
+        let end = start + content.len();
+        if bytes.len() < end {
+            bytes.resize(end, 0xFF);
+        }
+        bytes[start..end].copy_from_slice(content);
+    }
+
+    let fingerprint = match elf.section_by_name(FINGERPRINT_SECTION) {
+        Some(section) => {
+            let raw = section.data().context("reading fingerprint section")?;
+            let text = std::str::from_utf8(raw).context("fingerprint is not UTF-8")?;
+            Some(text.trim_end_matches('\0').to_owned())
+        }
+        None => None,
+    };
+
+    Ok((bytes, fingerprint))
+}
+
+/// Write `image_length` and `image_crc` into the header left by application
+fn fill_header(mut bytes: Vec<u8>) -> Result<(Vec<u8>, AppHeader)> {
+    let header_range = APP_HEADER_OFFSET..APP_HEADER_OFFSET + APP_HEADER_SIZE;
+    ensure!(
+        bytes.len() > header_range.end,
+        "image is too short for image header"
+    );
+    ensure!(
+        bytes.len() <= REMAIN_SIZE,
+        "image is {} bytes, application region is {REMAIN_SIZE} bytes",
+        bytes.len()
+    );
+
+    let mut header = read_header(&bytes[header_range.clone()]);
+    if header.magic != APP_HEADER_MAGIC {
+        bail!("image has no header at {APP_HEADER_OFFSET:#x}, place `AppHeader::new` there");
+    }
+    ensure!(
+        header.header_version == APP_HEADER_VERSION,
+        "unsupported header version {}",
+        header.header_version
+    );
+
+    header.image_length = (bytes.len() as u32).to_le_bytes();
+    header.image_crc = compute_image_crc(&bytes).to_le_bytes();
+    bytes[header_range].copy_from_slice(header_bytes(&header));
+
+    bytes.resize(bytes.len().div_ceil(PAD_SIZE) * PAD_SIZE, 0xFF);
+
+    Ok((bytes, header))
+}
+
+pub(crate) fn read_header(bytes: &[u8]) -> AppHeader {
+    assert_eq!(bytes.len(), APP_HEADER_SIZE);
+    // every field is byte array, no alignment is needed
+    unsafe { (bytes.as_ptr() as *const AppHeader).read_unaligned() }
+}
+
+pub fn header_bytes(header: &AppHeader) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(header as *const AppHeader as *const u8, APP_HEADER_SIZE) }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use laplus_boots_app::CRC_POLY_INIT;
+
+    use super::*;
+
+    /// Vector table filler, header from `AppHeader::new`, then some code
+    pub(crate) fn raw_image(len: usize) -> Vec<u8> {
+        let mut bytes: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        let header = AppHeader::new(*b"0.1.0\0\0\0");
+        bytes[APP_HEADER_OFFSET..APP_HEADER_OFFSET + APP_HEADER_SIZE]
+            .copy_from_slice(header_bytes(&header));
+
+        bytes
+    }
+
+    /// CRC peripheral as `crc_config()` of bootloader sets it up, bit by bit.
+    /// Every byte is bit-reversed on input, shifted MSB first with 0x04C11DB7
+    /// from `CRC_POLY_INIT`, output is not reversed.
+    fn peripheral_crc(bytes: &[u8]) -> u32 {
+        let mut crc = CRC_POLY_INIT;
+
+        for &byte in bytes {
+            crc ^= (byte.reverse_bits() as u32) << 24;
+            for _ in 0..8 {
+                crc = match crc & 0x8000_0000 {
+                    0 => crc << 1,
+                    _ => (crc << 1) ^ 0x04C1_1DB7,
+                };
+            }
+        }
+
+        crc
+    }
+
+    #[test]
+    fn peripheral_crc_check_value() {
+        assert_eq!(peripheral_crc(b"123456789"), 0xA612_7172);
+    }
+
+    #[test]
+    fn image_crc_skips_header() {
+        let bytes: Vec<u8> = (0..0x200).map(|i| (i % 251) as u8).collect();
+        let mut expected = bytes[..APP_HEADER_OFFSET].to_vec();
+        expected.extend_from_slice(&bytes[APP_HEADER_OFFSET + APP_HEADER_SIZE..]);
+
+        assert_eq!(compute_image_crc(&bytes), 0xEDCE_CC11);
+        assert_eq!(compute_image_crc(&bytes), peripheral_crc(&expected));
+    }
+
+    #[test]
+    fn fill_header_writes_length_and_crc() {
+        let (bytes, header) = fill_header(raw_image(1000)).unwrap();
+
+        assert_eq!(bytes.len(), 1024);
+        assert!(bytes[1000..].iter().all(|&x| x == 0xFF));
+        assert_eq!(header.image_length(), 1000);
+        assert_eq!(header.image_crc(), compute_image_crc(&bytes[..1000]));
+        assert_eq!(
+            header_bytes(&read_header(
+                &bytes[APP_HEADER_OFFSET..APP_HEADER_OFFSET + APP_HEADER_SIZE]
+            )),
+            header_bytes(&header)
+        );
+    }
+
+    #[test]
+    fn fill_header_rejects_oversized_image() {
+        assert!(fill_header(raw_image(REMAIN_SIZE)).is_ok());
+        assert!(fill_header(raw_image(REMAIN_SIZE + 1)).is_err());
+    }
+
+    #[test]
+    fn fill_header_rejects_missing_header() {
+        let mut bytes = raw_image(1000);
+        bytes[APP_HEADER_OFFSET] = 0;
+
+        assert!(fill_header(bytes).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Packs application ELF or binary into a single package for the flasher,
+//! so only images built for laplus-boots-rs with a valid header get flashed.
+
+mod image;
+mod package;
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+
+use crate::image::AppImage;
+use crate::package::{parse_fingerprint, Package};
+
+#[derive(Parser)]
+#[command(version, about)]
+enum Cli {
+    /// Make package out of application ELF or binary
+    Pack {
+        /// ELF, or raw binary from application start
+        input: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+        /// 32 bytes raw file, same key as `CIPHER_KEY` of bootloader
+        #[arg(long)]
+        cipher_key: PathBuf,
+        /// 32 bytes raw Ed25519 secret key file
+        #[arg(long)]
+        signing_key: PathBuf,
+        /// mp_fingerprint TOML, required for raw binary
+        #[arg(long)]
+        fingerprint: Option<PathBuf>,
+    },
+    /// Write Ed25519 public key of signing key, the flasher checks packages with it
+    PublicKey {
+        #[arg(long)]
+        signing_key: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Show package, signature is checked when verifying key is given
+    Info {
+        package: PathBuf,
+        #[arg(long)]
+        verifying_key: Option<PathBuf>,
+    },
+}
+
+fn read_key(path: &Path) -> Result<[u8; 32]> {
+    std::fs::read(path)
+        .with_context(|| format!("reading {}", path.display()))?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("{} is not 32 bytes key", path.display()))
+}
+
+fn main() -> Result<()> {
+    match Cli::parse() {
+        Cli::Pack {
+            input,
+            output,
+            cipher_key,
+            signing_key,
+            fingerprint,
+        } => {
+            let image = AppImage::load(&input)?;
+            let metadata = match (fingerprint, image.fingerprint.as_ref()) {
+                (Some(path), _) => std::fs::read_to_string(&path)
+                    .with_context(|| format!("reading {}", path.display()))?,
+                (None, Some(text)) => text.clone(),
+                (None, None) => anyhow::bail!("no .mp_fingerprint in input, give --fingerprint"),
+            };
+
+            let package = Package::new(&image, metadata, &read_key(&cipher_key)?)?;
+            let signing_key = SigningKey::from_bytes(&read_key(&signing_key)?);
+            std::fs::write(&output, package.to_signed_bytes(&signing_key))
+                .with_context(|| format!("writing {}", output.display()))?;
+
+            print_package(&package)
+        }
+        Cli::PublicKey {
+            signing_key,
+            output,
+        } => {
+            let signing_key = SigningKey::from_bytes(&read_key(&signing_key)?);
+            std::fs::write(&output, signing_key.verifying_key().as_bytes())
+                .with_context(|| format!("writing {}", output.display()))
+        }
+        Cli::Info {
+            package,
+            verifying_key,
+        } => {
+            let bytes = std::fs::read(&package)
+                .with_context(|| format!("reading {}", package.display()))?;
+            let verifying_key = match verifying_key {
+                Some(path) => Some(VerifyingKey::from_bytes(&read_key(&path)?)?),
+                None => None,
+            };
+
+            let package = Package::parse(&bytes, verifying_key.as_ref())?;
+            print_package(&package)?;
+            println!(
+                "signature   : {}",
+                match verifying_key {
+                    Some(_) => "valid",
+                    None => "not checked",
+                }
+            );
+
+            Ok(())
+        }
+    }
+}
+
+fn print_package(package: &Package) -> Result<()> {
+    let fingerprint = parse_fingerprint(&package.metadata)?;
+    let erased_blocks: u32 = package.erased_map.iter().map(|x| x.count_ones()).sum();
+
+    println!(
+        "model       : {} {}",
+        fingerprint.model_name, fingerprint.model_ver
+    );
+    println!("version     : {}", fingerprint.firmware_ver);
+    println!("git hash    : {}", fingerprint.firmware_git_hash);
+    println!(
+        "header      : {} (length {}, crc {:#010x})",
+        String::from_utf8_lossy(&package.header.fw_version).trim_end_matches('\0'),
+        package.header.image_length(),
+        package.header.image_crc()
+    );
+    println!(
+        "payload     : {} bytes, {} of 64 bytes blocks erased",
+        package.payload.len(),
+        erased_blocks
+    );
+
+    Ok(())
+}
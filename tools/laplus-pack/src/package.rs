@@ -0,0 +1,265 @@
+/*
+ * SPDX-FileCopyrightText: © 2025 Jinwoo Park (pmnxis@gmail.com)
+ *
+ * SPDX-License-Identifier: MIT OR Apache-2.0
+ */
+
+//! Package file taken by the flasher, every multi-byte field is little endian.
+//!
+//! | Size | Field |
+//! |-----:|-------|
+//! | 4 | magic `LPPK` |
+//! | 1 | package format version (`1`) |
+//! | 1 | reserved |
+//! | 2 | metadata length |
+//! | 4 | payload length |
+//! | 12 | nonce for `StartUpdate` with host nonce |
+//! | 32 | image header, plain copy of the one in payload |
+//! | 32 | SHA-256 of plain image |
+//! | n | metadata, `mp_fingerprint` TOML |
+//! | payload / 512 | erased map, bit per 64 bytes of payload that is all `0xFF` before encryption |
+//! | payload | encrypted image from application start |
+//! | 64 | Ed25519 signature over everything above |
+
+use anyhow::{bail, ensure, Context, Result};
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey, SIGNATURE_LENGTH};
+use laplus_boots_app::header::{AppHeader, APP_HEADER_SIZE};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::image::{header_bytes, read_header, AppImage, PAD_SIZE};
+
+pub const PACKAGE_MAGIC: [u8; 4] = *b"LPPK";
+pub const PACKAGE_VERSION: u8 = 1;
+const FIXED_LEN: usize = 4 + 1 + 1 + 2 + 4 + 12 + APP_HEADER_SIZE + 32;
+
+/// Same as `FirmwareFingerprint` of billmock-mptool
+#[derive(Deserialize)]
+pub struct FirmwareFingerprint {
+    pub model_name: String,
+    pub model_ver: String,
+    pub firmware_ver: String,
+    pub firmware_git_hash: String,
+}
+
+#[derive(Deserialize)]
+struct MpFingerprint {
+    firmware_fingerprint: FirmwareFingerprint,
+}
+
+pub fn parse_fingerprint(toml_text: &str) -> Result<FirmwareFingerprint> {
+    let parsed: MpFingerprint = toml::from_str(toml_text).context("parsing mp_fingerprint")?;
+
+    Ok(parsed.firmware_fingerprint)
+}
+
+pub struct Package {
+    pub nonce: [u8; 12],
+    pub header: AppHeader,
+    pub digest: [u8; 32],
+    pub metadata: String,
+    pub erased_map: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+impl Package {
+    /// Encrypt `image` with bootloader's `cipher_key`. Nonce comes from the image
+    /// digest, so the same image always gives the same package.
+    pub fn new(image: &AppImage, metadata: String, cipher_key: &[u8; 32]) -> Result<Self> {
+        parse_fingerprint(&metadata)?;
+        ensure!(metadata.len() <= u16::MAX as usize, "metadata is too long");
+
+        let digest: [u8; 32] = Sha256::digest(&image.bytes).into();
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&digest[..12]);
+
+        let erased_map = erased_map(&image.bytes);
+
+        // keystream position is the offset from application start, same as bootloader
+        let mut payload = image.bytes.clone();
+        ChaCha20::new(cipher_key.into(), &nonce.into()).apply_keystream(&mut payload);
+
+        Ok(Self {
+            nonce,
+            header: image.header,
+            digest,
+            metadata,
+            erased_map,
+            payload,
+        })
+    }
+
+    fn unsigned_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(
+            FIXED_LEN + self.metadata.len() + self.erased_map.len() + self.payload.len(),
+        );
+        ret.extend_from_slice(&PACKAGE_MAGIC);
+        ret.push(PACKAGE_VERSION);
+        ret.push(0);
+        ret.extend_from_slice(&(self.metadata.len() as u16).to_le_bytes());
+        ret.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        ret.extend_from_slice(&self.nonce);
+        ret.extend_from_slice(header_bytes(&self.header));
+        ret.extend_from_slice(&self.digest);
+        ret.extend_from_slice(self.metadata.as_bytes());
+        ret.extend_from_slice(&self.erased_map);
+        ret.extend_from_slice(&self.payload);
+
+        ret
+    }
+
+    pub fn to_signed_bytes(&self, signing_key: &SigningKey) -> Vec<u8> {
+        let mut ret = self.unsigned_bytes();
+        let signature = signing_key.sign(&ret);
+        ret.extend_from_slice(&signature.to_bytes());
+
+        ret
+    }
+
+    /// Parse package, signature is checked when `verifying_key` is given
+    pub fn parse(bytes: &[u8], verifying_key: Option<&VerifyingKey>) -> Result<Self> {
+        ensure!(
+            bytes.len() >= FIXED_LEN + SIGNATURE_LENGTH,
+            "package is too short"
+        );
+        ensure!(bytes[..4] == PACKAGE_MAGIC, "not a package");
+        if bytes[4] != PACKAGE_VERSION {
+            bail!("unsupported package version {}", bytes[4]);
+        }
+
+        let (signed, signature) = bytes.split_at(bytes.len() - SIGNATURE_LENGTH);
+        if let Some(key) = verifying_key {
+            let signature = Signature::from_slice(signature).context("broken signature")?;
+            key.verify(signed, &signature)
+                .context("signature doesn't match, package is modified or from other key")?;
+        }
+
+        let metadata_len = u16::from_le_bytes([signed[6], signed[7]]) as usize;
+        let payload_len = u32::from_le_bytes(signed[8..12].try_into()?) as usize;
+        let erased_map_len = erased_map_len(payload_len);
+        ensure!(
+            signed.len() == FIXED_LEN + metadata_len + erased_map_len + payload_len,
+            "package length doesn't match its fields"
+        );
+
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&signed[12..24]);
+        let header_end = 24 + APP_HEADER_SIZE;
+        let header = read_header(&signed[24..header_end]);
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&signed[header_end..FIXED_LEN]);
+
+        let metadata_end = FIXED_LEN + metadata_len;
+        let metadata = std::str::from_utf8(&signed[FIXED_LEN..metadata_end])
+            .context("metadata is not UTF-8")?
+            .to_owned();
+        let erased_map_end = metadata_end + erased_map_len;
+
+        Ok(Self {
+            nonce,
+            header,
+            digest,
+            metadata,
+            erased_map: signed[metadata_end..erased_map_end].to_vec(),
+            payload: signed[erased_map_end..].to_vec(),
+        })
+    }
+}
+
+const fn erased_map_len(payload_len: usize) -> usize {
+    payload_len.div_ceil(PAD_SIZE).div_ceil(8)
+}
+
+fn erased_map(image: &[u8]) -> Vec<u8> {
+    let mut ret = vec![0u8; erased_map_len(image.len())];
+
+    for (i, block) in image.chunks(PAD_SIZE).enumerate() {
+        if block.iter().all(|&x| x == 0xFF) {
+            ret[i / 8] |= 1 << (i % 8);
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use chacha20::cipher::StreamCipherSeek;
+
+    use super::*;
+    use crate::image::tests::raw_image;
+
+    const CIPHER_KEY: [u8; 32] = [0x42; 32];
+    const METADATA: &str = "[firmware_fingerprint]
+model_name = \"BillMock-HW\"
+model_ver = \"BILLMOCK-MINI-0V5\"
+firmware_ver = \"0.1.0\"
+firmware_git_hash = \"0000000\"
+";
+
+    fn package() -> Package {
+        let mut data = raw_image(4000);
+        // erased gap in the middle, as a linker leaves between sections
+        data[1024..2048].fill(0xFF);
+        let image = AppImage::from_data(data).unwrap();
+
+        Package::new(&image, METADATA.to_owned(), &CIPHER_KEY).unwrap()
+    }
+
+    #[test]
+    fn signed_round_trip() {
+        let package = package();
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let bytes = package.to_signed_bytes(&signing_key);
+
+        let parsed = Package::parse(&bytes, Some(&signing_key.verifying_key())).unwrap();
+
+        assert_eq!(parsed.nonce, package.nonce);
+        assert_eq!(header_bytes(&parsed.header), header_bytes(&package.header));
+        assert_eq!(parsed.digest, package.digest);
+        assert_eq!(parsed.metadata, package.metadata);
+        assert_eq!(parsed.erased_map, package.erased_map);
+        assert_eq!(parsed.payload, package.payload);
+        // blocks 16..32 are the erased gap
+        assert_eq!(&parsed.erased_map[2..4], &[0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn tampered_package_is_rejected() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let verifying_key = signing_key.verifying_key();
+        let bytes = package().to_signed_bytes(&signing_key);
+
+        let mut tampered = bytes.clone();
+        tampered[FIXED_LEN + 40] ^= 0x01;
+        assert!(Package::parse(&tampered, Some(&verifying_key)).is_err());
+        // still readable when nobody checks
+        assert!(Package::parse(&tampered, None).is_ok());
+
+        let other_key = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        assert!(Package::parse(&bytes, Some(&other_key)).is_err());
+    }
+
+    /// Bootloader decrypts every chunk after `cipher.seek(address - REMAIN_OFFSET)`
+    #[test]
+    fn payload_decrypts_at_any_chunk_offset() {
+        let plain = AppImage::from_data(raw_image(4000)).unwrap().bytes;
+        let package = Package::new(
+            &AppImage::from_data(raw_image(4000)).unwrap(),
+            METADATA.to_owned(),
+            &CIPHER_KEY,
+        )
+        .unwrap();
+
+        for offset in [0, PAD_SIZE, 1024, 3968] {
+            let mut chunk = package.payload[offset..offset + PAD_SIZE].to_vec();
+            let mut cipher = ChaCha20::new(&CIPHER_KEY.into(), &package.nonce.into());
+            cipher.seek(offset as u32);
+            cipher.apply_keystream(&mut chunk);
+
+            assert_eq!(chunk, plain[offset..offset + PAD_SIZE]);
+        }
+    }
+}